@@ -0,0 +1,700 @@
+use crate::config::Config;
+use crate::handler::{process_records, synthetic_record, NotificationResult};
+use crate::message::ObjectIdentification;
+use crate::sink::Notifier;
+use crate::store::{ObjectPage, ObjectStore};
+use crate::Outcome;
+use futures::stream::{self, StreamExt};
+use lambda_runtime::{Context, Error};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+// Validate the objects that are already in a bucket, e.g. after the rules changed. The objects
+// are listed a page at a time and the checkpoint is updated once a page is done, so an
+// interrupted backfill resumes after the last complete page. The objects that failed are kept
+// in the checkpoint and retried first when the backfill is run again.
+
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackfillOptions {
+    pub bucket: String,
+    pub prefix: String,
+    // Every version of the objects instead of the latest ones
+    pub all_versions: bool,
+    // Objects validated at the same time
+    pub concurrency: usize,
+    // Send the messages to the destinations, otherwise only the tags are updated
+    pub notify: bool,
+    // Only run the rules, nothing is written to the bucket
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillStatus {
+    Valid,
+    Invalid,
+    // Objects written by the workflow, e.g. quarantined or promoted copies
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BackfillResult {
+    pub key: String,
+    pub version_id: Option<String>,
+    pub status: BackfillStatus,
+    pub message: String,
+    pub notifications: Vec<NotificationResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BackfillSummary {
+    pub valid: u64,
+    pub invalid: u64,
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+impl BackfillSummary {
+    pub fn add(&mut self, status: BackfillStatus) {
+        match status {
+            BackfillStatus::Valid => self.valid += 1,
+            BackfillStatus::Invalid => self.invalid += 1,
+            BackfillStatus::Skipped => self.skipped += 1,
+            BackfillStatus::Failed => self.failed += 1,
+        }
+    }
+}
+
+// Position of the backfill in the listing of the bucket
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub bucket: String,
+    pub prefix: String,
+    pub all_versions: bool,
+    // The listing resumes after this key, and after this version when listing every version
+    pub key_marker: Option<String>,
+    pub version_id_marker: Option<String>,
+    pub done: bool,
+    pub summary: BackfillSummary,
+    // Objects that could not be validated, retried by the next run
+    #[serde(default)]
+    pub failed_objects: Vec<ObjectIdentification>,
+    // The failed versions that were not the current object of their key
+    #[serde(default)]
+    pub failed_noncurrent: BTreeSet<(String, String)>,
+}
+
+impl Checkpoint {
+    pub fn new(options: &BackfillOptions) -> Checkpoint {
+        Checkpoint {
+            bucket: options.bucket.clone(),
+            prefix: options.prefix.clone(),
+            all_versions: options.all_versions,
+            key_marker: None,
+            version_id_marker: None,
+            done: false,
+            summary: BackfillSummary::default(),
+            failed_objects: Vec::new(),
+            failed_noncurrent: BTreeSet::new(),
+        }
+    }
+
+    // Start from the beginning when the file doesn't exist yet
+    pub fn load(path: &Path, options: &BackfillOptions) -> Result<Checkpoint, Error> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Checkpoint::new(options))
+            }
+            Err(e) => {
+                return Err(Error::from(format!(
+                    "Original Error: {}; Could not read checkpoint {}",
+                    e,
+                    path.display()
+                )))
+            }
+        };
+        let checkpoint: Checkpoint = serde_json::from_str(&contents).map_err(|e| {
+            Error::from(format!(
+                "Original Error: {}; Could not parse checkpoint {}",
+                e,
+                path.display()
+            ))
+        })?;
+        if checkpoint.bucket != options.bucket
+            || checkpoint.prefix != options.prefix
+            || checkpoint.all_versions != options.all_versions
+        {
+            return Err(Error::from(format!(
+                "Checkpoint {} was written for s3://{}/{} (all versions: {}), not for s3://{}/{} (all versions: {})",
+                path.display(),
+                checkpoint.bucket,
+                checkpoint.prefix,
+                checkpoint.all_versions,
+                options.bucket,
+                options.prefix,
+                options.all_versions
+            )));
+        }
+        Ok(checkpoint)
+    }
+
+    // Written next to the file and renamed, an interruption never leaves half a checkpoint
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, serde_json::to_string_pretty(self)?)
+            .and_then(|_| std::fs::rename(&temporary, path))
+            .map_err(|e| {
+                Error::from(format!(
+                    "Original Error: {}; Could not write checkpoint {}",
+                    e,
+                    path.display()
+                ))
+            })
+    }
+}
+
+async fn list_page(
+    store: &dyn ObjectStore,
+    options: &BackfillOptions,
+    checkpoint: &Checkpoint,
+) -> Result<ObjectPage, Error> {
+    store
+        .list_objects(
+            &options.bucket,
+            &options.prefix,
+            options.all_versions,
+            checkpoint.key_marker.as_deref(),
+            checkpoint.version_id_marker.as_deref(),
+        )
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+                "Original Error: {}; Could not list Objects in s3://{}/{}",
+                original_error, options.bucket, options.prefix
+            ))
+        })
+}
+
+async fn backfill_object(
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+    options: &BackfillOptions,
+    object: &ObjectIdentification,
+    noncurrent: bool,
+    request_id: &str,
+) -> BackfillResult {
+    let result = |status: BackfillStatus, message: String| BackfillResult {
+        key: object.key.clone(),
+        version_id: object.version_id.clone(),
+        status,
+        message,
+        notifications: Vec::new(),
+    };
+    if config.is_internal_object(&object.bucket, &object.key) {
+        return result(
+            BackfillStatus::Skipped,
+            "Skipped object written by the workflow".to_string(),
+        );
+    }
+    if options.dry_run {
+        let record = synthetic_record(object);
+        let report = config
+            .for_object(&object.bucket, &object.key)
+            .validate_file(&record.s3);
+        let status = match report.outcome() {
+            Outcome::Success => BackfillStatus::Valid,
+            Outcome::Failure => BackfillStatus::Invalid,
+        };
+        return result(status, report.message());
+    }
+
+    // Listing the latest objects gives no version id, the tags go on the current version
+    let object = match &object.version_id {
+        Some(_) => object.clone(),
+        None => match latest_version(store, object).await {
+            Ok(object) => object,
+            Err(e) => return result(BackfillStatus::Failed, e.to_string()),
+        },
+    };
+    let record = synthetic_record(&object);
+    // Only the current object of a key is moved or promoted, the older versions are only
+    // tagged: the tombstone of a move would hide the current object, and every version would
+    // overwrite the same copy
+    let config = if noncurrent {
+        Cow::Owned(Config {
+            quarantine: None,
+            promotion: None,
+            links: None,
+            ..config.clone()
+        })
+    } else {
+        Cow::Borrowed(config)
+    };
+
+    let mut context = Context::default();
+    context.request_id = request_id.to_string();
+    let notifier = if options.notify { Some(notifier) } else { None };
+    match process_records(&[record], &context, store, notifier, &config).await {
+        Ok(mut records) => {
+            let record = records.remove(0);
+            let status = match record.outcome {
+                Some(Outcome::Success) => BackfillStatus::Valid,
+                Some(Outcome::Failure) => BackfillStatus::Invalid,
+                None => BackfillStatus::Skipped,
+            };
+            BackfillResult {
+                notifications: record.notifications,
+                ..result(status, record.message)
+            }
+        }
+        Err(e) => result(BackfillStatus::Failed, e.to_string()),
+    }
+}
+
+async fn latest_version(
+    store: &dyn ObjectStore,
+    object: &ObjectIdentification,
+) -> Result<ObjectIdentification, Error> {
+    let head = store
+        .head_object(&object.bucket, &object.key, None)
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+                "Original Error: {}; Could not get the latest version of s3://{}/{}",
+                original_error, object.bucket, object.key
+            ))
+        })?;
+    match head.version_id {
+        Some(version_id) => Ok(ObjectIdentification {
+            version_id: Some(version_id),
+            ..object.clone()
+        }),
+        None => Err(Error::from(format!(
+            "Object s3://{}/{} has no version ID defined, is versioning enabled in the bucket?",
+            object.bucket, object.key
+        ))),
+    }
+}
+
+// Validate the objects and count them in the checkpoint, the ones that failed are kept for a retry
+async fn backfill_objects(
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+    options: &BackfillOptions,
+    checkpoint: &mut Checkpoint,
+    page: &ObjectPage,
+    request_id: &str,
+) -> Vec<BackfillResult> {
+    let (objects, noncurrent) = (&page.objects, &page.noncurrent);
+    let is_noncurrent = |object: &ObjectIdentification| {
+        object.version_id.as_ref().is_some_and(|version_id| {
+            noncurrent.contains(&(object.key.clone(), version_id.clone()))
+        })
+    };
+    let results: Vec<BackfillResult> = stream::iter(objects.iter())
+        .map(|object| {
+            backfill_object(
+                store,
+                notifier,
+                config,
+                options,
+                object,
+                is_noncurrent(object),
+                request_id,
+            )
+        })
+        .buffered(options.concurrency.max(1))
+        .collect()
+        .await;
+    for (object, result) in objects.iter().zip(&results) {
+        checkpoint.summary.add(result.status);
+        if result.status == BackfillStatus::Failed {
+            checkpoint.failed_objects.push(object.clone());
+            if let (true, Some(version_id)) = (is_noncurrent(object), &object.version_id) {
+                checkpoint
+                    .failed_noncurrent
+                    .insert((object.key.clone(), version_id.clone()));
+            }
+        }
+    }
+    results
+}
+
+// Validate every object from the checkpoint on. The results of each page are handed to
+// on_page along with the updated checkpoint, which is where the caller reports and saves them.
+pub async fn backfill<F>(
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+    options: &BackfillOptions,
+    checkpoint: &mut Checkpoint,
+    request_id: &str,
+    mut on_page: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&[BackfillResult], &Checkpoint) -> Result<(), Error>,
+{
+    // The failures of an earlier run are retried before the listing resumes, they are
+    // only counted once
+    if !checkpoint.failed_objects.is_empty() {
+        let failed = ObjectPage {
+            objects: std::mem::take(&mut checkpoint.failed_objects),
+            noncurrent: std::mem::take(&mut checkpoint.failed_noncurrent),
+            next: None,
+        };
+        checkpoint.summary.failed = checkpoint
+            .summary
+            .failed
+            .saturating_sub(failed.objects.len() as u64);
+        let results = backfill_objects(
+            store, notifier, config, options, checkpoint, &failed, request_id,
+        )
+        .await;
+        on_page(&results, checkpoint)?;
+    }
+    while !checkpoint.done {
+        let page = list_page(store, options, checkpoint).await?;
+        let results = backfill_objects(
+            store, notifier, config, options, checkpoint, &page, request_id,
+        )
+        .await;
+        match page.next {
+            Some((key_marker, version_id_marker)) => {
+                checkpoint.key_marker = Some(key_marker);
+                checkpoint.version_id_marker = version_id_marker;
+            }
+            None => checkpoint.done = true,
+        }
+        on_page(&results, checkpoint)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests_checkpoint {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn options() -> BackfillOptions {
+        BackfillOptions {
+            bucket: "landing".to_string(),
+            prefix: "finance/".to_string(),
+            all_versions: true,
+            concurrency: DEFAULT_CONCURRENCY,
+            notify: false,
+            dry_run: false,
+        }
+    }
+
+    fn checkpoint_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("backfill-checkpoint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn test_missing_checkpoint_starts_from_the_beginning() {
+        let checkpoint = Checkpoint::load(&checkpoint_path("missing.json"), &options()).unwrap();
+        assert_eq!(checkpoint, Checkpoint::new(&options()));
+        assert_eq!(checkpoint.key_marker, None);
+        assert!(!checkpoint.done);
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let path = checkpoint_path("round-trip.json");
+        let mut checkpoint = Checkpoint::new(&options());
+        checkpoint.key_marker = Some("finance/1234-5678-9012-3456.txt".to_string());
+        checkpoint.version_id_marker = Some("v1".to_string());
+        checkpoint.summary.add(BackfillStatus::Valid);
+        checkpoint.summary.add(BackfillStatus::Invalid);
+        checkpoint.summary.add(BackfillStatus::Invalid);
+        checkpoint.save(&path).unwrap();
+
+        let loaded = Checkpoint::load(&path, &options()).unwrap();
+        assert_eq!(loaded, checkpoint);
+        assert_eq!(
+            loaded.summary,
+            BackfillSummary {
+                valid: 1,
+                invalid: 2,
+                skipped: 0,
+                failed: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_of_another_listing_is_rejected() {
+        let path = checkpoint_path("other-listing.json");
+        Checkpoint::new(&options()).save(&path).unwrap();
+        let other = BackfillOptions {
+            all_versions: false,
+            ..options()
+        };
+        let error = Checkpoint::load(&path, &other).unwrap_err();
+        assert!(error.to_string().contains("not for s3://landing/finance/"));
+    }
+}
+
+#[cfg(test)]
+mod tests_backfill {
+    use super::*;
+    use crate::memory::{MemoryMessageQueue, MemoryObjectStore, StoreOperation};
+    use crate::quarantine::QuarantineConfig;
+    use std::sync::Arc;
+
+    fn options(dry_run: bool) -> BackfillOptions {
+        BackfillOptions {
+            bucket: "landing".to_string(),
+            prefix: String::new(),
+            all_versions: true,
+            concurrency: 2,
+            notify: false,
+            dry_run,
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            success_queue_url: "https://sqs.eu-west-1.amazonaws.com/123456789012/success"
+                .to_string(),
+            failure_queue_url: "https://sqs.eu-west-1.amazonaws.com/123456789012/failure"
+                .to_string(),
+            quarantine: Some(serde_json::from_str::<QuarantineConfig>("{}").unwrap()),
+            ..Config::default()
+        }
+    }
+
+    async fn run(
+        store: &MemoryObjectStore,
+        queue: &Arc<MemoryMessageQueue>,
+        options: &BackfillOptions,
+    ) -> Vec<(String, BackfillStatus)> {
+        let notifier = Notifier::new(&aws_config::SdkConfig::builder().build())
+            .with_message_queue(queue.clone());
+        let mut checkpoint = Checkpoint::new(options);
+        let mut statuses = Vec::new();
+        backfill(
+            store,
+            &notifier,
+            &config(),
+            options,
+            &mut checkpoint,
+            "backfill-1",
+            |results, _| {
+                statuses.extend(
+                    results
+                        .iter()
+                        .map(|result| (result.key.clone(), result.status)),
+                );
+                Ok(())
+            },
+        )
+        .await
+        .unwrap();
+        assert!(checkpoint.done);
+        statuses
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_writes_nothing() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        store.insert("landing", "1234-5678-9012-3456.txt", b"data");
+        store.insert("landing", "report.csv", b"data");
+
+        let statuses = run(&store, &queue, &options(true)).await;
+        assert_eq!(
+            statuses,
+            vec![
+                ("1234-5678-9012-3456.txt".to_string(), BackfillStatus::Valid),
+                ("report.csv".to_string(), BackfillStatus::Invalid),
+            ]
+        );
+        assert!(store
+            .calls()
+            .iter()
+            .all(|call| call.operation == crate::memory::StoreOperation::ListObjects));
+        assert!(queue.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_tags_the_latest_versions() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        store.insert("landing", "1234-5678-9012-3456.txt", b"old");
+        let latest = store.insert("landing", "1234-5678-9012-3456.txt", b"data");
+        store.insert("landing", "report.csv", b"data");
+
+        let options = BackfillOptions {
+            all_versions: false,
+            ..options(false)
+        };
+        let statuses = run(&store, &queue, &options).await;
+        assert_eq!(
+            statuses,
+            vec![
+                ("1234-5678-9012-3456.txt".to_string(), BackfillStatus::Valid),
+                ("report.csv".to_string(), BackfillStatus::Invalid),
+            ]
+        );
+        assert_eq!(
+            store.tags("landing", "1234-5678-9012-3456.txt", Some(&latest)),
+            Some(crate::tags!["validated" => "true", "valid" => "true"])
+        );
+        // The invalid object was found and moved to quarantine
+        assert!(store
+            .object("landing", "quarantine/report.csv", None)
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_only_the_current_version_is_quarantined() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let old = store.insert("landing", "1234-5678-9012-3456.txt", b"");
+        let current = store.insert("landing", "1234-5678-9012-3456.txt", b"data");
+
+        let statuses = run(&store, &queue, &options(false)).await;
+        assert_eq!(
+            statuses,
+            vec![
+                ("1234-5678-9012-3456.txt".to_string(), BackfillStatus::Valid),
+                (
+                    "1234-5678-9012-3456.txt".to_string(),
+                    BackfillStatus::Invalid
+                ),
+            ]
+        );
+        // The old version is only tagged, the current one is still the object of the key
+        assert_eq!(
+            store.tags("landing", "1234-5678-9012-3456.txt", Some(&old)),
+            Some(crate::tags!["validated" => "true", "quarentine" => "true"])
+        );
+        assert_eq!(
+            store.tags("landing", "1234-5678-9012-3456.txt", None),
+            Some(crate::tags!["validated" => "true", "valid" => "true"])
+        );
+        assert_eq!(
+            store
+                .object("landing", "1234-5678-9012-3456.txt", None)
+                .unwrap()
+                .body,
+            b"data"
+        );
+        assert!(store
+            .object("landing", "1234-5678-9012-3456.txt", Some(&current))
+            .is_some());
+        assert!(store
+            .object("landing", "quarantine/1234-5678-9012-3456.txt", None)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failed_objects_are_retried_by_the_next_run() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        store.insert("landing", "1234-5678-9012-3456.txt", b"data");
+        store.insert("landing", "report.csv", b"data");
+        let notifier = Notifier::new(&aws_config::SdkConfig::builder().build())
+            .with_message_queue(queue.clone());
+        let options = BackfillOptions {
+            concurrency: 1,
+            ..options(false)
+        };
+        let mut checkpoint = Checkpoint::new(&options);
+        store.fail(StoreOperation::PutObjectTagging, 1, "SlowDown");
+
+        let mut statuses = Vec::new();
+        let mut on_page = |results: &[BackfillResult], _: &Checkpoint| {
+            statuses.extend(results.iter().map(|result| result.status));
+            Ok(())
+        };
+        backfill(
+            &store,
+            &notifier,
+            &config(),
+            &options,
+            &mut checkpoint,
+            "backfill-1",
+            &mut on_page,
+        )
+        .await
+        .unwrap();
+        assert!(checkpoint.done);
+        assert_eq!(checkpoint.failed_objects.len(), 1);
+        assert_eq!(checkpoint.failed_objects[0].key, "1234-5678-9012-3456.txt");
+        assert_eq!(checkpoint.summary.failed, 1);
+
+        // Running again retries the failure even though the listing is done
+        backfill(
+            &store,
+            &notifier,
+            &config(),
+            &options,
+            &mut checkpoint,
+            "backfill-2",
+            &mut on_page,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            statuses,
+            vec![
+                BackfillStatus::Failed,
+                BackfillStatus::Invalid,
+                BackfillStatus::Valid
+            ]
+        );
+        assert!(checkpoint.failed_objects.is_empty());
+        assert_eq!(
+            checkpoint.summary,
+            BackfillSummary {
+                valid: 1,
+                invalid: 1,
+                skipped: 0,
+                failed: 0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backfill_tags_without_notifying() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        store.insert("landing", "1234-5678-9012-3456.txt", b"data");
+        store.insert("landing", "report.csv", b"data");
+        // Left by an earlier run, must not be validated again
+        store.insert("landing", "quarantine/old.csv", b"data");
+
+        let statuses = run(&store, &queue, &options(false)).await;
+        assert_eq!(
+            statuses,
+            vec![
+                ("1234-5678-9012-3456.txt".to_string(), BackfillStatus::Valid),
+                ("quarantine/old.csv".to_string(), BackfillStatus::Skipped),
+                ("report.csv".to_string(), BackfillStatus::Invalid),
+            ]
+        );
+        assert_eq!(
+            store.tags("landing", "1234-5678-9012-3456.txt", None),
+            Some(vec![
+                ("validated".to_string(), "true".to_string()),
+                ("valid".to_string(), "true".to_string())
+            ])
+        );
+        assert!(store
+            .object("landing", "quarantine/report.csv", None)
+            .is_some());
+        assert!(queue.messages().is_empty());
+    }
+}
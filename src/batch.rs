@@ -0,0 +1,306 @@
+use crate::config::Config;
+use crate::sink::{Delivery, Destination, NotificationSink, Notifier};
+use crate::sqs::{message_size, OutboundMessage};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::warn;
+
+// Limits of a single SendMessageBatch, PublishBatch or PutEvents call
+pub const MAX_BATCH_ENTRIES: usize = 10;
+pub const MAX_BATCH_BYTES: usize = 256 * 1024;
+
+// How many times failed entries are sent again and the delay before the first retry
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(100);
+
+// A message waiting in the buffer, tagged with the index of the record that produced it
+#[derive(Debug, Clone)]
+struct PendingEntry {
+    record_index: usize,
+    message: OutboundMessage,
+}
+
+// Result of sending one buffered message, reported back to the record that produced it
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryResult {
+    pub record_index: usize,
+    pub destination: String,
+    // The message id on success, the error returned by the destination otherwise
+    pub result: Result<String, String>,
+}
+
+// Collects the outbound messages of an invocation and sends them grouped per
+// destination, in batches.
+#[derive(Debug, Default)]
+pub struct MessageBuffer {
+    destinations: BTreeMap<Destination, Vec<PendingEntry>>,
+}
+
+impl MessageBuffer {
+    pub fn new() -> MessageBuffer {
+        MessageBuffer::default()
+    }
+
+    pub fn push(
+        &mut self,
+        destination: Destination,
+        record_index: usize,
+        message: OutboundMessage,
+    ) {
+        self.destinations
+            .entry(destination)
+            .or_default()
+            .push(PendingEntry {
+                record_index,
+                message,
+            });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.destinations.is_empty()
+    }
+
+    // Send every buffered message, retrying only the entries that failed
+    pub async fn flush(self, notifier: &Notifier, config: &Config) -> Vec<EntryResult> {
+        let mut results = Vec::new();
+        for (destination, entries) in self.destinations {
+            let sink = notifier.sink(config, &destination);
+            for batch in split_batches(entries) {
+                results.extend(send_batch(sink.as_ref(), destination.name(), batch).await);
+            }
+        }
+        results
+    }
+}
+
+// Split the entries of a destination in batches of at most 10 entries and 256 KB
+fn split_batches(entries: Vec<PendingEntry>) -> Vec<Vec<PendingEntry>> {
+    let mut batches: Vec<Vec<PendingEntry>> = Vec::new();
+    let mut current: Vec<PendingEntry> = Vec::new();
+    let mut current_bytes = 0;
+    for entry in entries {
+        let size = message_size(&entry.message);
+        if !current.is_empty()
+            && (current.len() == MAX_BATCH_ENTRIES || current_bytes + size > MAX_BATCH_BYTES)
+        {
+            batches.push(std::mem::take(&mut current));
+            current_bytes = 0;
+        }
+        current_bytes += size;
+        current.push(entry);
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+// Send one batch, entries that failed with a retryable error are sent again with a backoff
+async fn send_batch(
+    sink: &dyn NotificationSink,
+    destination: &str,
+    batch: Vec<PendingEntry>,
+) -> Vec<EntryResult> {
+    let mut results: Vec<Option<Result<String, String>>> = vec![None; batch.len()];
+    let mut pending: Vec<usize> = (0..batch.len()).collect();
+
+    for attempt in 0..MAX_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+        }
+        let messages: Vec<&OutboundMessage> =
+            pending.iter().map(|&index| &batch[index].message).collect();
+        let deliveries = sink.send_batch(&messages).await;
+
+        let mut retry = Vec::new();
+        for (&index, delivery) in pending.iter().zip(deliveries) {
+            match delivery {
+                Delivery::Sent(message_id) => results[index] = Some(Ok(message_id)),
+                Delivery::Failed { error, retryable } => {
+                    results[index] = Some(Err(error));
+                    if retryable {
+                        retry.push(index);
+                    }
+                }
+            }
+        }
+        if retry.is_empty() {
+            break;
+        }
+        warn!(
+            "Retrying {} entries of a batch to {}",
+            retry.len(),
+            destination
+        );
+        pending = retry;
+    }
+
+    batch
+        .into_iter()
+        .zip(results)
+        .map(|(entry, result)| EntryResult {
+            record_index: entry.record_index,
+            destination: destination.to_string(),
+            result: result.unwrap_or_else(|| Err("Missing result for entry".to_string())),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests_batch_split {
+    use super::*;
+
+    fn entry(record_index: usize, body_size: usize) -> PendingEntry {
+        PendingEntry {
+            record_index,
+            message: OutboundMessage {
+                body: "x".repeat(body_size),
+                attributes: BTreeMap::new(),
+                trace_header: None,
+                group_id: "group".to_string(),
+                deduplication_id: "dedup".to_string(),
+            },
+        }
+    }
+
+    fn record_indexes(batches: &[Vec<PendingEntry>]) -> Vec<Vec<usize>> {
+        batches
+            .iter()
+            .map(|batch| batch.iter().map(|entry| entry.record_index).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_at_ten_entries() {
+        let batches = split_batches((0..23).map(|i| entry(i, 10)).collect());
+        assert_eq!(
+            batches.iter().map(|b| b.len()).collect::<Vec<_>>(),
+            vec![10, 10, 3]
+        );
+    }
+
+    #[test]
+    fn test_split_at_256_kb() {
+        let batches = split_batches(vec![
+            entry(0, 100 * 1024),
+            entry(1, 100 * 1024),
+            entry(2, 100 * 1024),
+            entry(3, 10),
+        ]);
+        assert_eq!(record_indexes(&batches), vec![vec![0, 1], vec![2, 3]]);
+    }
+
+    #[test]
+    fn test_oversized_message_gets_its_own_batch() {
+        let batches = split_batches(vec![entry(0, 10), entry(1, 300 * 1024), entry(2, 10)]);
+        assert_eq!(record_indexes(&batches), vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_buffer_groups_per_destination() {
+        let mut buffer = MessageBuffer::new();
+        assert!(buffer.is_empty());
+        for index in 0..3 {
+            buffer.push(
+                Destination::sqs("https://sqs/a.fifo"),
+                index,
+                entry(index, 1).message,
+            );
+        }
+        let topic = Destination::Sns {
+            topic_arn: "arn:aws:sns:eu-west-1:123456789012:b".to_string(),
+        };
+        buffer.push(topic.clone(), 1, entry(1, 1).message);
+        assert_eq!(buffer.destinations.len(), 2);
+        assert_eq!(
+            buffer.destinations[&Destination::sqs("https://sqs/a.fifo")].len(),
+            3
+        );
+        assert_eq!(buffer.destinations[&topic].len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod tests_batch_retry {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    // Fails every message with a retryable error on the first call, and with a
+    // non-retryable error the bodies starting with "bad"
+    struct FlakySink {
+        calls: Mutex<Vec<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl NotificationSink for FlakySink {
+        async fn send_batch(&self, messages: &[&OutboundMessage]) -> Vec<Delivery> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(messages.iter().map(|m| m.body.clone()).collect());
+            let first_call = calls.len() == 1;
+            messages
+                .iter()
+                .map(|message| {
+                    if message.body.starts_with("bad") {
+                        Delivery::Failed {
+                            error: "InvalidParameterValue".to_string(),
+                            retryable: false,
+                        }
+                    } else if first_call && message.body.ends_with("flaky") {
+                        Delivery::Failed {
+                            error: "InternalError".to_string(),
+                            retryable: true,
+                        }
+                    } else {
+                        Delivery::Sent(format!("id-{}", message.body))
+                    }
+                })
+                .collect()
+        }
+    }
+
+    fn entry(record_index: usize, body: &str) -> PendingEntry {
+        PendingEntry {
+            record_index,
+            message: OutboundMessage {
+                body: body.to_string(),
+                attributes: BTreeMap::new(),
+                trace_header: None,
+                group_id: "group".to_string(),
+                deduplication_id: "dedup".to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_retryable_failures_are_sent_again() {
+        let sink = FlakySink {
+            calls: Mutex::new(Vec::new()),
+        };
+        let results = send_batch(
+            &sink,
+            "https://sqs/a.fifo",
+            vec![entry(0, "ok"), entry(1, "bad"), entry(2, "flaky")],
+        )
+        .await;
+
+        assert_eq!(
+            *sink.calls.lock().unwrap(),
+            vec![vec!["ok", "bad", "flaky"], vec!["flaky"]]
+        );
+        assert_eq!(
+            results
+                .iter()
+                .map(|r| (r.record_index, r.result.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, Ok("id-ok".to_string())),
+                (1, Err("InvalidParameterValue".to_string())),
+                (2, Ok("id-flaky".to_string())),
+            ]
+        );
+        assert!(results
+            .iter()
+            .all(|r| r.destination == "https://sqs/a.fifo"));
+    }
+}
@@ -0,0 +1,232 @@
+use aws_lambda_events::event::s3::S3Event;
+use aws_sdk_s3::Client as S3Client;
+use chrono::Utc;
+use clap::{Args, Parser, Subcommand};
+use lambda_runtime::{Context, Error, LambdaEvent};
+use rust_lambda_s3_tagging_sqs::backfill::{
+    backfill, BackfillOptions, Checkpoint, DEFAULT_CONCURRENCY,
+};
+use rust_lambda_s3_tagging_sqs::config::Config;
+use rust_lambda_s3_tagging_sqs::dry_run::object_store;
+use rust_lambda_s3_tagging_sqs::handler::{function_handler, synthetic_record, NotificationResult};
+use rust_lambda_s3_tagging_sqs::sink::Notifier;
+use rust_lambda_s3_tagging_sqs::store::{ObjectStore, S3Store};
+use rust_lambda_s3_tagging_sqs::workflow::{describe_object, quarantine, release, status};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::info;
+
+// Inspect and fix the validation state of an object. Uses the same configuration as the
+// Lambda (CONFIG_FILE and the env vars) and the AWS credentials of the environment.
+#[derive(Parser, Debug)]
+#[command(
+    name = "operator",
+    about = "Inspect, release or re-quarantine validated objects"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the tags and workflow state of an object version
+    Status(ObjectArgs),
+    /// Tag a quarantined object valid and send a success notification
+    Release {
+        #[command(flatten)]
+        object: ObjectArgs,
+        #[arg(long, default_value = "File released from quarantine by an operator")]
+        message: String,
+    },
+    /// Tag an object for quarantine and send a failure notification
+    Quarantine {
+        #[command(flatten)]
+        object: ObjectArgs,
+        #[arg(long, default_value = "File quarantined by an operator")]
+        reason: String,
+    },
+    /// Run the validation again, as if the object had just been uploaded
+    Revalidate(ObjectArgs),
+    /// Validate the objects already in a bucket, e.g. after the rules changed
+    Backfill(BackfillArgs),
+}
+
+#[derive(Args, Debug)]
+struct ObjectArgs {
+    #[arg(long)]
+    bucket: String,
+    #[arg(long)]
+    key: String,
+    /// Defaults to the latest version
+    #[arg(long)]
+    version_id: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct BackfillArgs {
+    #[arg(long)]
+    bucket: String,
+    #[arg(long, default_value = "")]
+    prefix: String,
+    /// Validate every version of the objects instead of the latest ones
+    #[arg(long)]
+    all_versions: bool,
+    /// Objects validated at the same time
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+    /// Send the messages to the destinations, otherwise only the tags are updated
+    #[arg(long)]
+    notify: bool,
+    /// Only print the result of the rules, nothing is tagged or sent
+    #[arg(long)]
+    dry_run: bool,
+    /// File where the progress is saved, the backfill resumes from it when it exists
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+}
+
+async fn run_backfill(
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+    args: &BackfillArgs,
+    request_id: &str,
+) -> Result<(), Error> {
+    let options = BackfillOptions {
+        bucket: args.bucket.clone(),
+        prefix: args.prefix.clone(),
+        all_versions: args.all_versions,
+        concurrency: args.concurrency,
+        notify: args.notify,
+        dry_run: args.dry_run,
+    };
+    let mut checkpoint = match &args.checkpoint {
+        Some(path) => Checkpoint::load(path, &options)?,
+        None => Checkpoint::new(&options),
+    };
+    // One JSON line per object, the output of large buckets can be processed as a stream
+    backfill(
+        store,
+        notifier,
+        config,
+        &options,
+        &mut checkpoint,
+        request_id,
+        |results, checkpoint| {
+            for result in results {
+                println!("{}", serde_json::to_string(result)?);
+            }
+            // A dry run must not move the checkpoint of the real run
+            match &args.checkpoint {
+                Some(path) if !args.dry_run => checkpoint.save(path),
+                _ => Ok(()),
+            }
+        },
+    )
+    .await?;
+
+    let summary = &checkpoint.summary;
+    info!(
+        "Backfill done: {} valid, {} invalid, {} skipped, {} failed",
+        summary.valid, summary.invalid, summary.skipped, summary.failed
+    );
+    if summary.failed > 0 {
+        return Err(Error::from(format!(
+            "{} objects could not be validated",
+            summary.failed
+        )));
+    }
+    Ok(())
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Error> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<(), Error> {
+    let sdk_config = aws_config::load_from_env().await;
+    let config = Config::load()?;
+    // With DRY_RUN the commands only log what they would write and send
+    let store = object_store(Arc::new(S3Store::new(S3Client::new(&sdk_config))), &config);
+    let notifier = Notifier::new(&sdk_config);
+    // Identifies the operator action in the exc_id of the messages
+    let request_id = format!("operator-{}", Utc::now().format("%Y%m%dT%H%M%S%.3fZ"));
+
+    let object_args = match &cli.command {
+        Command::Backfill(args) => {
+            return run_backfill(store.as_ref(), &notifier, &config, args, &request_id).await
+        }
+        Command::Status(object)
+        | Command::Revalidate(object)
+        | Command::Release { object, .. }
+        | Command::Quarantine { object, .. } => object,
+    };
+    let object = describe_object(
+        store.as_ref(),
+        &object_args.bucket,
+        &object_args.key,
+        object_args.version_id.as_deref(),
+    )
+    .await?;
+
+    match &cli.command {
+        Command::Status(_) => print_json(&status(store.as_ref(), &config, &object).await?),
+        Command::Release { message, .. } => print_json(
+            &release(
+                store.as_ref(),
+                &notifier,
+                &config,
+                &object,
+                &request_id,
+                message,
+            )
+            .await?
+            .into_iter()
+            .map(NotificationResult::from)
+            .collect::<Vec<_>>(),
+        ),
+        Command::Quarantine { reason, .. } => print_json(
+            &quarantine(
+                store.as_ref(),
+                &notifier,
+                &config,
+                &object,
+                &request_id,
+                reason,
+            )
+            .await?
+            .into_iter()
+            .map(NotificationResult::from)
+            .collect::<Vec<_>>(),
+        ),
+        Command::Revalidate(_) => {
+            let mut context = Context::default();
+            context.request_id = request_id;
+            let event = LambdaEvent::new(
+                S3Event {
+                    records: vec![synthetic_record(&object)],
+                },
+                context,
+            );
+            print_json(&function_handler(event, store.as_ref(), &notifier, &config).await?)
+        }
+        Command::Backfill(_) => unreachable!("the backfill doesn't work on a single object"),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .with_writer(std::io::stderr)
+        .init();
+
+    if let Err(e) = run(Cli::parse()).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
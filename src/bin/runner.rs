@@ -0,0 +1,234 @@
+use aws_lambda_events::event::s3::S3Event;
+use aws_sdk_s3::Client as S3Client;
+use chrono::Utc;
+use clap::Parser;
+use lambda_runtime::{Context, Error, LambdaEvent};
+use rust_lambda_s3_tagging_sqs::config::Config;
+use rust_lambda_s3_tagging_sqs::dry_run::object_store;
+use rust_lambda_s3_tagging_sqs::handler::{function_handler, Response};
+use rust_lambda_s3_tagging_sqs::local::s3_events_from_json;
+use rust_lambda_s3_tagging_sqs::memory::{
+    MemoryMessageQueue, MemoryObjectStore, SentMessage, StoreCall,
+};
+use rust_lambda_s3_tagging_sqs::message::ObjectIdentification;
+use rust_lambda_s3_tagging_sqs::sink::Notifier;
+use rust_lambda_s3_tagging_sqs::store::{ObjectStore, S3Store};
+use rust_lambda_s3_tagging_sqs::workflow::{status, ObjectStatus};
+use serde::Serialize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::warn;
+
+// Objects created in memory are filled up to this size, the rules use the size of the event
+const MAX_MEMORY_OBJECT_BYTES: usize = 1024 * 1024;
+
+// Replay event fixtures through the handler without deploying it. The clients use the
+// configuration of the environment, point them at MinIO or LocalStack with --endpoint-url
+// or keep everything in memory with --in-memory.
+#[derive(Parser, Debug)]
+#[command(
+    name = "runner",
+    about = "Run the validation handler on S3 event fixtures"
+)]
+struct Cli {
+    /// JSON files of the events, read from stdin when none is given or for -
+    files: Vec<PathBuf>,
+    /// Endpoint of the S3, SQS, SNS and EventBridge APIs, e.g. http://localhost:4566
+    #[arg(long, conflicts_with = "in_memory")]
+    endpoint_url: Option<String>,
+    /// Use an in-memory S3 and SQS, the objects of the events are created before each invocation
+    #[arg(long)]
+    in_memory: bool,
+}
+
+// What an invocation returned and what it left behind
+#[derive(Serialize, Debug)]
+struct Invocation {
+    source: String,
+    request_id: String,
+    response: Option<Response>,
+    error: Option<String>,
+    // Tags and state of the objects of the event once the handler returned
+    objects: Vec<ObjectStatus>,
+    // Every S3 call and SQS message of the invocation, recorded in memory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store_calls: Option<Vec<StoreCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    messages: Option<Vec<SentMessage>>,
+}
+
+// The in-memory S3 and SQS of --in-memory
+struct Fakes {
+    store: Arc<MemoryObjectStore>,
+    queue: Arc<MemoryMessageQueue>,
+}
+
+impl Fakes {
+    // The handler expects the objects of the event to exist
+    fn create_objects(&self, objects: &[ObjectIdentification]) {
+        for object in objects {
+            let version_id = object.version_id.as_deref();
+            if self
+                .store
+                .object(&object.bucket, &object.key, version_id)
+                .is_some()
+            {
+                continue;
+            }
+            let size =
+                (object.size.unwrap_or_default().max(0) as usize).min(MAX_MEMORY_OBJECT_BYTES);
+            let body = vec![0; size];
+            match version_id {
+                Some(version_id) => {
+                    self.store
+                        .insert_version(&object.bucket, &object.key, version_id, &body)
+                }
+                None => {
+                    self.store.insert(&object.bucket, &object.key, &body);
+                }
+            }
+        }
+    }
+}
+
+fn read_source(path: &Path) -> Result<String, Error> {
+    let mut contents = String::new();
+    if path.as_os_str() == "-" {
+        std::io::stdin().read_to_string(&mut contents)?;
+    } else {
+        contents = std::fs::read_to_string(path).map_err(|e| {
+            Error::from(format!(
+                "Original Error: {}; Could not read {}",
+                e,
+                path.display()
+            ))
+        })?;
+    }
+    Ok(contents)
+}
+
+async fn invoke(
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+    fakes: Option<&Fakes>,
+    source: String,
+    request_id: String,
+    event: S3Event,
+) -> Invocation {
+    let objects: Vec<ObjectIdentification> = event
+        .records
+        .iter()
+        .filter_map(|record| ObjectIdentification::from_record(record).ok())
+        .collect();
+    if let Some(fakes) = fakes {
+        fakes.create_objects(&objects);
+    }
+
+    let mut context = Context::default();
+    context.request_id = request_id.clone();
+    context.deadline = (Utc::now().timestamp_millis() + 15 * 60 * 1000) as u64;
+    let (response, error) =
+        match function_handler(LambdaEvent::new(event, context), store, notifier, config).await {
+            Ok(response) => (Some(response), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+    let store_calls = fakes.map(|fakes| fakes.store.take_calls());
+    let messages = fakes.map(|fakes| fakes.queue.take_messages());
+
+    // Objects moved to quarantine are gone from their original key, they can't be described
+    let mut statuses = Vec::new();
+    for object in objects {
+        match status(store, config, &object).await {
+            Ok(object_status) => statuses.push(object_status),
+            Err(e) => warn!("{}", e),
+        }
+    }
+    if let Some(fakes) = fakes {
+        fakes.store.take_calls();
+    }
+    Invocation {
+        source,
+        request_id,
+        response,
+        error,
+        objects: statuses,
+        store_calls,
+        messages,
+    }
+}
+
+async fn run(cli: Cli) -> Result<bool, Error> {
+    let mut loader = aws_config::from_env();
+    if let Some(endpoint_url) = &cli.endpoint_url {
+        loader = loader.endpoint_url(endpoint_url);
+    }
+    let sdk_config = loader.load().await;
+    let fakes = cli.in_memory.then(|| Fakes {
+        store: Arc::new(MemoryObjectStore::new()),
+        queue: Arc::new(MemoryMessageQueue::new()),
+    });
+    let (store, notifier): (Arc<dyn ObjectStore>, Notifier) = match &fakes {
+        Some(fakes) => (
+            fakes.store.clone(),
+            Notifier::new(&sdk_config).with_message_queue(fakes.queue.clone()),
+        ),
+        // MinIO and LocalStack don't serve buckets as subdomains of the endpoint
+        None => (
+            Arc::new(S3Store::new(S3Client::from_conf(
+                aws_sdk_s3::config::Builder::from(&sdk_config)
+                    .force_path_style(cli.endpoint_url.is_some())
+                    .build(),
+            ))),
+            Notifier::new(&sdk_config),
+        ),
+    };
+    let config = Config::load()?;
+    let store = object_store(store, &config);
+
+    let files = if cli.files.is_empty() {
+        vec![PathBuf::from("-")]
+    } else {
+        cli.files
+    };
+    let run_id = Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+    let mut succeeded = true;
+    let mut invocation_index = 0;
+    for path in &files {
+        for event in s3_events_from_json(&read_source(path)?)? {
+            invocation_index += 1;
+            let invocation = invoke(
+                store.as_ref(),
+                &notifier,
+                &config,
+                fakes.as_ref(),
+                path.display().to_string(),
+                format!("local-{}-{}", run_id, invocation_index),
+                event,
+            )
+            .await;
+            succeeded &= invocation.error.is_none();
+            println!("{}", serde_json::to_string_pretty(&invocation)?);
+        }
+    }
+    Ok(succeeded)
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .with_writer(std::io::stderr)
+        .init();
+
+    match run(Cli::parse()).await {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
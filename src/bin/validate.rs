@@ -0,0 +1,123 @@
+use clap::{Parser, ValueEnum};
+use rust_lambda_s3_tagging_sqs::local::{validate_local_file, LocalReport};
+use rust_lambda_s3_tagging_sqs::rules::RuleSet;
+use rust_lambda_s3_tagging_sqs::{Outcome, Severity};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+// Check files with the rules of the validation Lambda before uploading them.
+// Exits with 1 when a file is invalid and 2 when a file can't be read.
+#[derive(Parser, Debug)]
+#[command(
+    name = "validate",
+    about = "Check local files against the upload validation rules"
+)]
+struct Cli {
+    /// Files to check
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// Prefix of the key the files will be uploaded under, e.g. finance/
+    #[arg(long, default_value = "")]
+    prefix: String,
+    /// JSON file of the rule set, like the rules section of the Lambda config
+    #[arg(long)]
+    rules: Option<PathBuf>,
+    /// Severity of a check as configured in the Lambda, e.g. invalid_name_format=warning
+    #[arg(long = "severity", value_name = "CODE=SEVERITY", value_parser = parse_severity)]
+    severities: Vec<(String, Severity)>,
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    format: Format,
+}
+
+fn parse_severity(value: &str) -> Result<(String, Severity), String> {
+    let (code, severity) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected CODE=SEVERITY, got {}", value))?;
+    let severity = severity.parse().map_err(|e| format!("{}", e))?;
+    Ok((code.to_string(), severity))
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Human,
+    Json,
+}
+
+// The severities of the command line are checked along with the rule set, an unknown error
+// code would otherwise be ignored
+fn load_rules(path: Option<&Path>, severities: &[(String, Severity)]) -> Result<RuleSet, String> {
+    let mut rules: RuleSet = match path {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+            serde_json::from_str(&contents)
+                .map_err(|e| format!("Invalid rule set {}: {}", path.display(), e))?
+        }
+        None => RuleSet::default(),
+    };
+    rules.severities.extend(severities.iter().cloned());
+    let error_messages = rules.check();
+    if !error_messages.is_empty() {
+        return Err(format!("Invalid rule set: {}", error_messages.join(", ")));
+    }
+    Ok(rules)
+}
+
+fn print_human(report: &LocalReport) {
+    match report.outcome {
+        Outcome::Success => println!("OK      {}: {}", report.path, report.message),
+        Outcome::Failure => println!("INVALID {}", report.path),
+    }
+    for issue in &report.issues {
+        println!(
+            "        {} {}: {}",
+            issue.severity.as_str(),
+            issue.code,
+            issue.message
+        );
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let rules = match load_rules(cli.rules.as_deref(), &cli.severities) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let mut reports = Vec::new();
+    let mut unreadable = false;
+    for path in &cli.files {
+        match validate_local_file(path, &cli.prefix, &rules) {
+            Ok(report) => reports.push(report),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                unreadable = true;
+            }
+        }
+    }
+
+    match cli.format {
+        Format::Human => reports.iter().for_each(print_human),
+        Format::Json => match serde_json::to_string_pretty(&reports) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(2);
+            }
+        },
+    }
+
+    if unreadable {
+        ExitCode::from(2)
+    } else if reports
+        .iter()
+        .any(|report| report.outcome == Outcome::Failure)
+    {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
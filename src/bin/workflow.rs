@@ -0,0 +1,163 @@
+use aws_sdk_s3::Client as S3Client;
+use chrono::Utc;
+use lambda_http::http::{Method, StatusCode};
+use lambda_http::{run, service_fn, Body, Error, Request, RequestExt, Response};
+use rust_lambda_s3_tagging_sqs::config::Config;
+use rust_lambda_s3_tagging_sqs::dry_run::object_store;
+use rust_lambda_s3_tagging_sqs::links::{confirmation_page, LinkAction, LinksConfig, SignedLink};
+use rust_lambda_s3_tagging_sqs::sink::Notifier;
+use rust_lambda_s3_tagging_sqs::store::{ObjectStore, S3Store};
+use rust_lambda_s3_tagging_sqs::workflow::{describe_object, reject, release, status, ObjectState};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+// Answer with a short plain text page, the links are opened in a browser
+fn text_response(status: StatusCode, text: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(Body::from(text.to_string()))?)
+}
+
+fn html_response(text: String) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(Body::from(text))?)
+}
+
+// Handle the continue and abort links of failure messages, /<...>/continue or /<...>/abort.
+// Opening a link shows a confirmation page, the action only runs when it is posted.
+async fn function_handler(
+    event: Request,
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+    links: &LinksConfig,
+) -> Result<Response<Body>, Error> {
+    let action = event
+        .uri()
+        .path()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let params = event.query_string_parameters();
+    let link = match SignedLink::from_query(&action, |name| params.first(name).map(str::to_string))
+    {
+        Ok(link) => link,
+        Err(e) => return text_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    if let Err(e) = link.verify(&links.secret()?, Utc::now().timestamp()) {
+        warn!(
+            "Refused {} link for s3://{}/{}: {}",
+            action, link.bucket, link.key, e
+        );
+        return text_response(StatusCode::FORBIDDEN, &e.to_string());
+    }
+    match *event.method() {
+        Method::GET | Method::HEAD => return html_response(confirmation_page(&link)),
+        Method::POST => {}
+        _ => {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header("Allow", "GET, HEAD, POST")
+                .body(Body::from("Method not allowed"))?)
+        }
+    }
+
+    let object =
+        match describe_object(store, &link.bucket, &link.key, link.version_id.as_deref()).await {
+            Ok(object) => object,
+            Err(e) => {
+                error!("{}", e);
+                return text_response(StatusCode::NOT_FOUND, "Object not found");
+            }
+        };
+    // A link can only be used once, while the object is quarantined and not rejected yet
+    match status(store, config, &object).await {
+        Ok(object_status) if object_status.state == ObjectState::Quarantined => {}
+        Ok(object_status) => {
+            warn!(
+                "Refused {} link for s3://{}/{} in state {:?}",
+                action, object.bucket, object.key, object_status.state
+            );
+            return text_response(
+                StatusCode::CONFLICT,
+                "The file is no longer in quarantine, the link was already used",
+            );
+        }
+        Err(e) => {
+            error!("{}", e);
+            return text_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "The action could not be completed, please try again",
+            );
+        }
+    }
+    let result = match link.action {
+        LinkAction::Continue => release(
+            store,
+            notifier,
+            config,
+            &object,
+            &event.lambda_context().request_id,
+            "File released from quarantine",
+        )
+        .await
+        .map(|_| "The file was released from quarantine"),
+        LinkAction::Abort => reject(store, config, &object)
+            .await
+            .map(|_| "The file was rejected"),
+    };
+    match result {
+        Ok(text) => {
+            info!(
+                "{} s3://{}/{} versionId: {}",
+                text,
+                object.bucket,
+                object.key,
+                object.version_id.as_deref().unwrap_or("null")
+            );
+            text_response(StatusCode::OK, text)
+        }
+        Err(e) => {
+            error!("{}", e);
+            text_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "The action could not be completed, please try again",
+            )
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let config = aws_config::load_from_env().await;
+    // Same configuration as the validation Lambda, only the links secret is used from the
+    // links section so the base URL doesn't have to point to this function
+    let runtime_config = Config::load()?;
+    let store = object_store(
+        Arc::new(S3Store::new(S3Client::new(&config))),
+        &runtime_config,
+    );
+    let notifier = Notifier::new(&config);
+    let links = runtime_config
+        .links
+        .clone()
+        .unwrap_or_else(|| LinksConfig::new(""));
+    links.secret()?;
+
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        // disable printing the name of the module in every log line.
+        .with_target(false)
+        // disabling time is handy because CloudWatch will add the ingestion time.
+        .without_time()
+        .init();
+
+    run(service_fn(|event: Request| {
+        function_handler(event, store.as_ref(), &notifier, &runtime_config, &links)
+    }))
+    .await
+}
@@ -0,0 +1,266 @@
+use crate::routing::criteria_match;
+use crate::{Outcome, Severity};
+use serde::Deserialize;
+
+// A category rule. Like a route, every criterion that is set must match for the rule to apply
+// and an unset criterion matches anything.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryRule {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub outcome: Option<Outcome>,
+    #[serde(default)]
+    pub error_code: Option<String>,
+    // Highest severity of the failed checks, a valid file without warnings has none
+    #[serde(default)]
+    pub severity: Option<Severity>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+    // Overrides the workflow name of the message, the first matching rule that sets one wins
+    #[serde(default)]
+    pub workflow: Option<String>,
+}
+
+impl CategoryRule {
+    fn matches(
+        &self,
+        key: &str,
+        outcome: Outcome,
+        severity: Option<Severity>,
+        error_codes: &[&str],
+    ) -> bool {
+        if let Some(expected) = self.severity {
+            if Some(expected) != severity {
+                return false;
+            }
+        }
+        criteria_match(
+            self.prefix.as_deref(),
+            self.outcome,
+            self.error_code.as_deref(),
+            key,
+            outcome,
+            error_codes,
+        )
+    }
+}
+
+// Maps (key prefix, outcome, severity, error code) to the categories of the message, so the teams
+// owning a prefix or a rule are the ones paged. All matching rules contribute their
+// categories, the static categories are used when none match.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(transparent)]
+pub struct CategoryRules {
+    pub rules: Vec<CategoryRule>,
+}
+
+impl CategoryRules {
+    pub fn categories(
+        &self,
+        key: &str,
+        outcome: Outcome,
+        severity: Option<Severity>,
+        error_codes: &[&str],
+        default_categories: &[String],
+    ) -> Vec<String> {
+        let mut categories: Vec<String> = Vec::new();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(key, outcome, severity, error_codes))
+        {
+            for category in &rule.categories {
+                if !categories.contains(category) {
+                    categories.push(category.clone());
+                }
+            }
+        }
+        if categories.is_empty() {
+            categories = default_categories.to_vec();
+        }
+        categories
+    }
+
+    pub fn workflow<'a>(
+        &'a self,
+        key: &str,
+        outcome: Outcome,
+        severity: Option<Severity>,
+        error_codes: &[&str],
+        default_workflow: &'a str,
+    ) -> &'a str {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(key, outcome, severity, error_codes))
+            .find_map(|rule| rule.workflow.as_deref())
+            .unwrap_or(default_workflow)
+    }
+
+    // Report every rule that could never change a message
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule.categories.is_empty() && rule.workflow.is_none() {
+                error_messages.push(format!(
+                    "Category rule {} has no categories and no workflow",
+                    index
+                ));
+            }
+            if rule.categories.iter().any(|c| c.trim().is_empty()) {
+                error_messages.push(format!(
+                    "Category rule {}: categories must not be empty",
+                    index
+                ));
+            }
+            if rule
+                .workflow
+                .as_ref()
+                .is_some_and(|workflow| workflow.trim().is_empty())
+            {
+                error_messages.push(format!(
+                    "Category rule {}: workflow name must not be empty",
+                    index
+                ));
+            }
+            if rule.error_code.is_some() && rule.outcome == Some(Outcome::Success) {
+                error_messages.push(format!(
+                    "Category rule {} matches an error code on success, it can never apply",
+                    index
+                ));
+            }
+            // An invalid file always has an error, a valid one never does
+            let severity_outcome = rule.severity.map(|severity| match severity {
+                Severity::Error => Outcome::Failure,
+                _ => Outcome::Success,
+            });
+            if rule.outcome.is_some()
+                && severity_outcome.is_some()
+                && rule.outcome != severity_outcome
+            {
+                error_messages.push(format!(
+                    "Category rule {} matches a severity that can't occur with its outcome",
+                    index
+                ));
+            }
+        }
+        error_messages
+    }
+}
+
+#[cfg(test)]
+mod tests_category_rules {
+    use super::*;
+
+    fn rules() -> CategoryRules {
+        serde_json::from_str(
+            r#"[
+                { "prefix": "finance/", "categories": ["FIN-OPS"], "workflow": "Finance_Validation" },
+                { "prefix": "finance/", "outcome": "failure", "categories": ["FIN-OPS", "FIN-ONCALL"] },
+                { "error_code": "invalid_name_format", "categories": ["DATA-QUALITY"] }
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    fn defaults() -> Vec<String> {
+        vec!["CD-TECH".to_string(), "AM-DEVS".to_string()]
+    }
+
+    #[test]
+    fn test_no_matching_rule_uses_default_categories() {
+        let rules = rules();
+        assert_eq!(
+            rules.categories(
+                "hr/a.txt",
+                Outcome::Failure,
+                Some(Severity::Error),
+                &["empty_file"],
+                &defaults()
+            ),
+            defaults()
+        );
+        assert_eq!(
+            rules.workflow(
+                "hr/a.txt",
+                Outcome::Failure,
+                Some(Severity::Error),
+                &[],
+                "Validation_Workflow"
+            ),
+            "Validation_Workflow"
+        );
+    }
+
+    #[test]
+    fn test_matching_rules_are_combined_without_duplicates() {
+        assert_eq!(
+            rules().categories(
+                "finance/a-b.txt",
+                Outcome::Failure,
+                Some(Severity::Error),
+                &["invalid_name_format"],
+                &defaults()
+            ),
+            vec!["FIN-OPS", "FIN-ONCALL", "DATA-QUALITY"]
+        );
+        assert_eq!(
+            rules().categories("finance/a.txt", Outcome::Success, None, &[], &defaults()),
+            vec!["FIN-OPS"]
+        );
+        assert_eq!(
+            rules().workflow(
+                "finance/a.txt",
+                Outcome::Success,
+                None,
+                &[],
+                "Validation_Workflow"
+            ),
+            "Finance_Validation"
+        );
+    }
+
+    #[test]
+    fn test_rule_on_severity() {
+        let rules: CategoryRules =
+            serde_json::from_str(r#"[{ "severity": "warning", "categories": ["RULE-ROLLOUT"] }]"#)
+                .unwrap();
+        assert_eq!(
+            rules.categories(
+                "a.txt",
+                Outcome::Success,
+                Some(Severity::Warning),
+                &[],
+                &defaults()
+            ),
+            vec!["RULE-ROLLOUT"]
+        );
+        assert_eq!(
+            rules.categories("a.txt", Outcome::Success, None, &[], &defaults()),
+            defaults()
+        );
+    }
+
+    #[test]
+    fn test_check_reports_unusable_rules() {
+        let rules: CategoryRules = serde_json::from_str(
+            r#"[
+                { "prefix": "finance/" },
+                { "outcome": "success", "error_code": "empty_file", "categories": [" "], "workflow": "" },
+                { "outcome": "failure", "severity": "warning", "categories": ["OPS"] }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules.check(),
+            vec![
+                "Category rule 0 has no categories and no workflow",
+                "Category rule 1: categories must not be empty",
+                "Category rule 1: workflow name must not be empty",
+                "Category rule 1 matches an error code on success, it can never apply",
+                "Category rule 2 matches a severity that can't occur with its outcome",
+            ]
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use crate::config::MessageFormat;
+use crate::message::CloudEvent;
+use crate::sqs::{message_size, OutboundMessage};
+use crate::store::ObjectStore;
+use lambda_runtime::Error;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Largest message SQS accepts, body and attributes included
+pub const MAX_MESSAGE_BYTES: usize = 256 * 1024;
+
+// Where oversized message bodies are written. The prefix must not trigger the validation
+// Lambda, objects written under it in the landing bucket are skipped by the handler.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ClaimCheckConfig {
+    pub bucket: String,
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    // Messages larger than this are offloaded, defaults to the SQS limit
+    #[serde(default = "default_threshold_bytes")]
+    pub threshold_bytes: usize,
+}
+
+fn default_prefix() -> String {
+    "claim-check/".to_string()
+}
+
+fn default_threshold_bytes() -> usize {
+    MAX_MESSAGE_BYTES
+}
+
+impl ClaimCheckConfig {
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        if self.bucket.is_empty() {
+            error_messages.push("Claim check bucket must not be empty".to_string());
+        }
+        if self.threshold_bytes == 0 || self.threshold_bytes > MAX_MESSAGE_BYTES {
+            error_messages.push(format!(
+                "Claim check threshold must be between 1 and {} bytes",
+                MAX_MESSAGE_BYTES
+            ));
+        }
+        error_messages
+    }
+
+    // Objects written by the claim check must not be validated themselves
+    pub fn contains(&self, bucket: &str, key: &str) -> bool {
+        self.bucket == bucket && key.starts_with(self.prefix.as_str())
+    }
+}
+
+/// Location of a message body that was too large for SQS.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ClaimCheck {
+    pub bucket: String,
+    pub key: String,
+    /// Size of the stored body in bytes.
+    pub size: usize,
+    /// Hex encoded SHA-256 of the stored body.
+    pub sha256: String,
+}
+
+/// Message sent instead of a body that was too large for SQS, resolve it with `resolve_claim_check`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ClaimCheckMessage {
+    pub claim_check: ClaimCheck,
+}
+
+impl ClaimCheck {
+    fn for_body(config: &ClaimCheckConfig, name: &str, body: &str) -> ClaimCheck {
+        let sha256 = hex::encode(Sha256::digest(body.as_bytes()));
+        ClaimCheck {
+            bucket: config.bucket.clone(),
+            key: format!("{}{}-{}.json", config.prefix, name, sha256),
+            size: body.len(),
+            sha256,
+        }
+    }
+
+    // The pointer carried by a message body, in a CloudEvents envelope or not, None for a
+    // regular message
+    pub fn from_body(body: &str) -> Option<ClaimCheck> {
+        serde_json::from_str::<ClaimCheckMessage>(body)
+            .or_else(|_| {
+                serde_json::from_str::<CloudEvent<ClaimCheckMessage>>(body)
+                    .map(|envelope| envelope.data)
+            })
+            .ok()
+            .map(|message| message.claim_check)
+    }
+
+    pub fn verify(&self, body: &[u8]) -> Result<(), Error> {
+        if body.len() != self.size || hex::encode(Sha256::digest(body)) != self.sha256 {
+            return Err(Error::from(format!(
+                "Claim check s3://{}/{} does not match its size or checksum",
+                self.bucket, self.key
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Write the body of an oversized message to S3 and replace it by a pointer.
+// The object is named after the deduplication id and the checksum of the body: a retry
+// overwrites the same object, while a revalidation with another report writes a new one and
+// leaves the body of the earlier pointers in place. A body in a CloudEvents envelope is stored
+// whole and the pointer is sent in the same envelope, only its data changes.
+pub async fn offload_if_oversized(
+    store: &dyn ObjectStore,
+    config: Option<&ClaimCheckConfig>,
+    format: MessageFormat,
+    message: OutboundMessage,
+) -> Result<OutboundMessage, Error> {
+    let config = match config {
+        Some(config) if message_size(&message) > config.threshold_bytes => config,
+        _ => return Ok(message),
+    };
+    let claim_check = ClaimCheck::for_body(config, &message.deduplication_id, &message.body);
+    store
+        .put_object(
+            &claim_check.bucket,
+            &claim_check.key,
+            "application/json",
+            message.body.clone().into_bytes(),
+        )
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+                "Original Error: {}; Could not write claim check to s3://{}/{}",
+                original_error, claim_check.bucket, claim_check.key
+            ))
+        })?;
+
+    let pointer = ClaimCheckMessage { claim_check };
+    let body = match format {
+        MessageFormat::Plain => serde_json::to_string(&pointer)?,
+        MessageFormat::CloudEvents => {
+            let envelope: CloudEvent<serde_json::Value> = serde_json::from_str(&message.body)?;
+            serde_json::to_string(&CloudEvent {
+                specversion: envelope.specversion,
+                id: envelope.id,
+                source: envelope.source,
+                event_type: envelope.event_type,
+                subject: envelope.subject,
+                time: envelope.time,
+                datacontenttype: envelope.datacontenttype,
+                // The data is no longer a validation message
+                dataschema: None,
+                data: pointer,
+            })?
+        }
+    };
+    let mut attributes = message.attributes;
+    attributes.insert("claim_check".to_string(), "true".to_string());
+    Ok(OutboundMessage {
+        body,
+        attributes,
+        ..message
+    })
+}
+
+// For consumers: return the full message body, fetching it from S3 when the message is a claim check
+pub async fn resolve_claim_check(store: &dyn ObjectStore, body: &str) -> Result<String, Error> {
+    let claim_check = match ClaimCheck::from_body(body) {
+        Some(claim_check) => claim_check,
+        None => return Ok(body.to_string()),
+    };
+    let bytes = store
+        .get_object(&claim_check.bucket, &claim_check.key)
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+                "Original Error: {}; Could not read claim check s3://{}/{}",
+                original_error, claim_check.bucket, claim_check.key
+            ))
+        })?;
+    claim_check.verify(&bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+// JSON Schema of the pointer message, committed to schemas/ for consumers
+pub fn claim_check_json_schema() -> String {
+    serde_json::to_string_pretty(&schema_for!(ClaimCheckMessage)).unwrap()
+}
+
+// JSON Schema of the pointer message wrapped in a CloudEvents envelope
+pub fn claim_check_cloudevent_json_schema() -> String {
+    serde_json::to_string_pretty(&schema_for!(CloudEvent<ClaimCheckMessage>)).unwrap()
+}
+
+#[cfg(test)]
+mod tests_claim_check {
+    use super::*;
+
+    fn config() -> ClaimCheckConfig {
+        serde_json::from_str(r#"{ "bucket": "reports" }"#).unwrap()
+    }
+
+    #[test]
+    fn test_claim_check_round_trip() {
+        let body = r#"{"message":"File is valid"}"#;
+        let claim_check = ClaimCheck::for_body(&config(), "abc", body);
+        assert_eq!(
+            claim_check.key,
+            format!("claim-check/abc-{}.json", claim_check.sha256)
+        );
+        assert_eq!(claim_check.size, body.len());
+
+        let pointer = serde_json::to_string(&ClaimCheckMessage {
+            claim_check: claim_check.clone(),
+        })
+        .unwrap();
+        assert_eq!(ClaimCheck::from_body(&pointer), Some(claim_check.clone()));
+        assert!(claim_check.verify(body.as_bytes()).is_ok());
+        assert!(claim_check.verify(b"tampered").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_another_body_does_not_overwrite_earlier_pointers() {
+        let store = crate::memory::MemoryObjectStore::new();
+        let config = ClaimCheckConfig {
+            threshold_bytes: 10,
+            ..config()
+        };
+        let first = OutboundMessage {
+            deduplication_id: "abc".to_string(),
+            body: r#"{"message":"File is valid"}"#.to_string(),
+            attributes: Default::default(),
+            trace_header: None,
+            group_id: "landing".to_string(),
+        };
+        let second = OutboundMessage {
+            body: r#"{"message":"File is not valid"}"#.to_string(),
+            ..first.clone()
+        };
+        let first = offload_if_oversized(&store, Some(&config), MessageFormat::Plain, first)
+            .await
+            .unwrap();
+        let second = offload_if_oversized(&store, Some(&config), MessageFormat::Plain, second)
+            .await
+            .unwrap();
+        assert_ne!(first.body, second.body);
+        assert_eq!(
+            resolve_claim_check(&store, &first.body).await.unwrap(),
+            r#"{"message":"File is valid"}"#
+        );
+        assert_eq!(
+            resolve_claim_check(&store, &second.body).await.unwrap(),
+            r#"{"message":"File is not valid"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cloudevents_pointer_keeps_the_envelope() {
+        let store = crate::memory::MemoryObjectStore::new();
+        let config = ClaimCheckConfig {
+            threshold_bytes: 10,
+            ..config()
+        };
+        let body = serde_json::json!({
+            "specversion": "1.0",
+            "id": "request-1:report.csv:v1",
+            "source": "urn:validation",
+            "type": "com.example.validation.failure",
+            "subject": "report.csv",
+            "time": "2023-02-01T10:15:30Z",
+            "datacontenttype": "application/json",
+            "dataschema": "https://example.com/validation-message.schema.json",
+            "data": { "message": "File is not valid" }
+        })
+        .to_string();
+        let message = OutboundMessage {
+            deduplication_id: "abc".to_string(),
+            body: body.clone(),
+            attributes: Default::default(),
+            trace_header: None,
+            group_id: "landing".to_string(),
+        };
+        let pointer =
+            offload_if_oversized(&store, Some(&config), MessageFormat::CloudEvents, message)
+                .await
+                .unwrap();
+        let envelope: CloudEvent<ClaimCheckMessage> = serde_json::from_str(&pointer.body).unwrap();
+        assert_eq!(envelope.id, "request-1:report.csv:v1");
+        assert_eq!(envelope.event_type, "com.example.validation.failure");
+        assert_eq!(envelope.subject.as_deref(), Some("report.csv"));
+        assert_eq!(envelope.dataschema, None);
+        assert_eq!(
+            ClaimCheck::from_body(&pointer.body),
+            Some(envelope.data.claim_check)
+        );
+        // The whole envelope is stored
+        assert_eq!(
+            resolve_claim_check(&store, &pointer.body).await.unwrap(),
+            body
+        );
+    }
+
+    #[test]
+    fn test_regular_message_is_not_a_claim_check() {
+        assert_eq!(
+            ClaimCheck::from_body(r#"{"message":"File is valid"}"#),
+            None
+        );
+        assert_eq!(ClaimCheck::from_body("not json"), None);
+    }
+
+    #[test]
+    fn test_config_defaults_and_checks() {
+        let config = config();
+        assert_eq!(config.threshold_bytes, MAX_MESSAGE_BYTES);
+        assert!(config.check().is_empty());
+        assert!(config.contains("reports", "claim-check/abc.json"));
+        assert!(!config.contains("landing", "claim-check/abc.json"));
+        let too_large = ClaimCheckConfig {
+            threshold_bytes: MAX_MESSAGE_BYTES + 1,
+            ..config
+        };
+        assert_eq!(too_large.check().len(), 1);
+    }
+
+    #[test]
+    fn test_claim_check_schema_is_up_to_date() {
+        let schemas = [
+            (
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/schemas/claim-check.schema.json"
+                ),
+                include_str!("../schemas/claim-check.schema.json"),
+                claim_check_json_schema(),
+            ),
+            (
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/schemas/claim-check.cloudevent.schema.json"
+                ),
+                include_str!("../schemas/claim-check.cloudevent.schema.json"),
+                claim_check_cloudevent_json_schema(),
+            ),
+        ];
+        for (path, committed, generated) in schemas {
+            if std::env::var("UPDATE_SCHEMAS").is_ok() {
+                std::fs::write(path, format!("{}\n", generated)).unwrap();
+                continue;
+            }
+            assert_eq!(
+                committed.trim_end(),
+                generated,
+                "{} is out of date, run the tests with UPDATE_SCHEMAS=1",
+                path
+            );
+        }
+    }
+}
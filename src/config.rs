@@ -0,0 +1,1014 @@
+use crate::categories::CategoryRules;
+use crate::claim_check::ClaimCheckConfig;
+use crate::links::LinksConfig;
+use crate::promotion::PromotionConfig;
+use crate::quarantine::QuarantineConfig;
+use crate::routing::RoutingTable;
+use crate::rules::RuleSet;
+use crate::shadow::ShadowConfig;
+use crate::sink::Destination;
+use crate::sqs::{Queue, QueueSettings};
+use crate::tenants::{TenantProfile, Tenants};
+use crate::{Outcome, Severity, ValidationReport};
+use aws_lambda_events::s3::S3Entity;
+use lambda_runtime::Error;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+// Name of the environment variable pointing to an optional JSON config file
+pub const CONFIG_FILE_ENV: &str = "CONFIG_FILE";
+
+// S3 allows tag keys of up to 128 unicode characters, SQS the same for message group ids
+const MAX_TAG_KEY_LENGTH: usize = 128;
+pub(crate) const MAX_MESSAGE_GROUP_ID_LENGTH: usize = 128;
+
+// Names of the tags applied to the object during the validation workflow
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TagNames {
+    pub validating: String,
+    pub validated: String,
+    pub valid: String,
+    pub quarantine: String,
+    // Applied when the abort link of a quarantined object is followed
+    pub rejected: String,
+    // Applied to valid objects that failed a check with a warning severity
+    pub warnings: String,
+    // Holds the tenant profile on quarantined copies, whose location may belong to another
+    // tenant. It is not namespaced, the tenant is not known before it is read.
+    pub tenant: String,
+    // Holds the bucket an object was moved from on copies in another quarantine bucket, so a
+    // released file can be copied back. It is not namespaced either.
+    pub origin: String,
+}
+
+impl Default for TagNames {
+    fn default() -> Self {
+        TagNames {
+            validating: "validating".to_string(),
+            validated: "validated".to_string(),
+            valid: "valid".to_string(),
+            quarantine: "quarentine".to_string(),
+            rejected: "rejected".to_string(),
+            warnings: "validation:warnings".to_string(),
+            tenant: "validation:tenant".to_string(),
+            origin: "validation:origin".to_string(),
+        }
+    }
+}
+
+// Layout of the SQS message body
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    // The ValidationMessageBody JSON as is
+    Plain,
+    // The ValidationMessageBody wrapped in a CloudEvents 1.0 structured mode envelope
+    CloudEvents,
+}
+
+impl std::str::FromStr for MessageFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(MessageFormat::Plain),
+            "cloudevents" => Ok(MessageFormat::CloudEvents),
+            other => Err(Error::from(format!(
+                "Unknown message format {}, expected plain or cloudevents",
+                other
+            ))),
+        }
+    }
+}
+
+// How the FIFO message group id is chosen, messages of a group are delivered in order
+// and one at a time so a narrower group gives more throughput.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageGroupStrategy {
+    // Every message uses message_group_id
+    Fixed,
+    // One group per object key
+    Key,
+    // One group per key prefix, objects at the bucket root use message_group_id
+    Prefix,
+    // One group per Prod ID, the file name without its extension
+    ProductId,
+}
+
+impl std::str::FromStr for MessageGroupStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(MessageGroupStrategy::Fixed),
+            "key" => Ok(MessageGroupStrategy::Key),
+            "prefix" => Ok(MessageGroupStrategy::Prefix),
+            "product_id" => Ok(MessageGroupStrategy::ProductId),
+            other => Err(Error::from(format!(
+                "Unknown message group strategy {}, expected fixed, key, prefix or product_id",
+                other
+            ))),
+        }
+    }
+}
+
+// Attributes of the CloudEvents envelope
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CloudEventsConfig {
+    pub source: String,
+    // The outcome is appended to the prefix, e.g. com.example.s3.validation.failure
+    pub type_prefix: String,
+    pub dataschema: Option<String>,
+}
+
+impl Default for CloudEventsConfig {
+    fn default() -> Self {
+        CloudEventsConfig {
+            source: "rust-lambda-s3-tagging-sqs".to_string(),
+            type_prefix: "com.example.s3.validation".to_string(),
+            dataschema: None,
+        }
+    }
+}
+
+// Runtime configuration, built once at cold start and shared by every invocation.
+// Values are read from the optional config file first and then overridden by env vars.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub success_queue_url: String,
+    pub failure_queue_url: String,
+    pub workflow: String,
+    // Used when no category rule matches
+    pub categories: Vec<String>,
+    // Categories and workflow name per prefix, outcome and failed rule
+    pub category_rules: CategoryRules,
+    // The checks run against every object and their severities
+    pub rules: RuleSet,
+    // A rule set evaluated next to the primary one to report divergent verdicts, disabled when not set
+    pub shadow: Option<ShadowConfig>,
+    pub message_group_id: String,
+    pub message_group_strategy: MessageGroupStrategy,
+    pub tags: TagNames,
+    pub routes: RoutingTable,
+    // Per queue settings keyed by queue URL
+    pub queues: BTreeMap<String, QueueSettings>,
+    pub message_format: MessageFormat,
+    pub cloudevents: CloudEventsConfig,
+    // Offload messages too large for SQS to S3, disabled when not set
+    pub claim_check: Option<ClaimCheckConfig>,
+    // Signed continue/abort links in failure messages, disabled when not set
+    pub links: Option<LinksConfig>,
+    // Move invalid objects to a quarantine location, they are only tagged when not set
+    pub quarantine: Option<QuarantineConfig>,
+    // Copy valid objects to a curated location, disabled when not set
+    pub promotion: Option<PromotionConfig>,
+    // Validate as usual but only log the tags, copies, deletions and messages instead of making them
+    pub dry_run: bool,
+    // Profiles of the teams sharing the deployment keyed by bucket and prefix, every object
+    // uses this configuration when not set
+    pub tenants: Option<Tenants>,
+    // Profile applied by for_object, not read from the config file
+    #[serde(skip)]
+    pub tenant: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            success_queue_url: String::new(),
+            failure_queue_url: String::new(),
+            workflow: "Validation_Workflow".to_string(),
+            categories: vec!["CD-TECH".to_string(), "AM-DEVS".to_string()],
+            category_rules: CategoryRules::default(),
+            rules: RuleSet::default(),
+            shadow: None,
+            message_group_id: "ValidationGroup".to_string(),
+            message_group_strategy: MessageGroupStrategy::Fixed,
+            tags: TagNames::default(),
+            routes: RoutingTable::default(),
+            queues: BTreeMap::new(),
+            message_format: MessageFormat::Plain,
+            cloudevents: CloudEventsConfig::default(),
+            claim_check: None,
+            links: None,
+            quarantine: None,
+            promotion: None,
+            dry_run: false,
+            tenants: None,
+            tenant: None,
+        }
+    }
+}
+
+impl Config {
+    // Load the configuration from the process environment and the file referenced by CONFIG_FILE
+    pub fn load() -> Result<Config, Error> {
+        let file_contents =
+            match std::env::var(CONFIG_FILE_ENV) {
+                Ok(path) => Some(std::fs::read_to_string(&path).map_err(|e| {
+                    Error::from(format!("Could not read config file {}: {}", path, e))
+                })?),
+                Err(_) => None,
+            };
+        Config::from_sources(file_contents.as_deref(), |name| std::env::var(name).ok())
+    }
+
+    // Build the configuration from the contents of a config file and an env var lookup,
+    // then validate it so a misconfigured function fails at cold start instead of mid-workflow.
+    pub fn from_sources<F>(file_contents: Option<&str>, env: F) -> Result<Config, Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut config = match file_contents {
+            Some(contents) => serde_json::from_str::<Config>(contents)
+                .map_err(|e| Error::from(format!("Invalid config file: {}", e)))?,
+            None => Config::default(),
+        };
+        config.apply_env(env)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env<F>(&mut self, env: F) -> Result<(), Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if let Some(url) = env("SUCCESS_QUEUE_URL") {
+            self.success_queue_url = url;
+        }
+        if let Some(url) = env("FAILURE_QUEUE_URL") {
+            self.failure_queue_url = url;
+        }
+        if let Some(workflow) = env("WORKFLOW_NAME") {
+            self.workflow = workflow;
+        }
+        // Categories are given as a comma separated list, e.g. "CD-TECH,AM-DEVS"
+        if let Some(categories) = env("CATEGORIES") {
+            self.categories = categories
+                .split(',')
+                .map(|category| category.trim().to_string())
+                .collect();
+        }
+        if let Some(group_id) = env("MESSAGE_GROUP_ID") {
+            self.message_group_id = group_id;
+        }
+        if let Some(strategy) = env("MESSAGE_GROUP_STRATEGY") {
+            self.message_group_strategy = strategy.parse()?;
+        }
+        if let Some(tag) = env("VALIDATING_TAG") {
+            self.tags.validating = tag;
+        }
+        if let Some(tag) = env("VALIDATED_TAG") {
+            self.tags.validated = tag;
+        }
+        if let Some(tag) = env("VALID_TAG") {
+            self.tags.valid = tag;
+        }
+        if let Some(tag) = env("QUARANTINE_TAG") {
+            self.tags.quarantine = tag;
+        }
+        if let Some(tag) = env("REJECTED_TAG") {
+            self.tags.rejected = tag;
+        }
+        if let Some(tag) = env("WARNINGS_TAG") {
+            self.tags.warnings = tag;
+        }
+        if let Some(tag) = env("TENANT_TAG") {
+            self.tags.tenant = tag;
+        }
+        if let Some(tag) = env("ORIGIN_TAG") {
+            self.tags.origin = tag;
+        }
+        // Checks that only warn, e.g. "invalid_name_format,non_numeric_name"
+        if let Some(codes) = env("WARNING_CODES") {
+            for code in codes
+                .split(',')
+                .map(str::trim)
+                .filter(|code| !code.is_empty())
+            {
+                self.rules
+                    .severities
+                    .insert(code.to_string(), Severity::Warning);
+            }
+        }
+        // Defer failure notifications, the failure queues have to be standard queues. The
+        // failure queues of the tenant profiles are deferred too.
+        if let Some(delay) = env("FAILURE_QUEUE_DELAY_SECONDS") {
+            let delay_seconds = delay.parse::<i32>().map_err(|_| {
+                Error::from(format!(
+                    "FAILURE_QUEUE_DELAY_SECONDS is not a number: {}",
+                    delay
+                ))
+            })?;
+            let tenant_failure_queue_urls = self
+                .tenants
+                .iter()
+                .flat_map(|tenants| tenants.profiles.values())
+                .filter_map(|profile| profile.failure_queue_url.clone());
+            let failure_queue_urls: Vec<String> = std::iter::once(self.failure_queue_url.clone())
+                .chain(tenant_failure_queue_urls)
+                .collect();
+            for url in failure_queue_urls {
+                self.queues.entry(url).or_default().delay_seconds = Some(delay_seconds);
+            }
+        }
+        if let Some(format) = env("MESSAGE_FORMAT") {
+            self.message_format = format.parse()?;
+        }
+        if let Some(dry_run) = env("DRY_RUN") {
+            self.dry_run = match dry_run.as_str() {
+                "true" | "1" => true,
+                "false" | "0" | "" => false,
+                other => {
+                    return Err(Error::from(format!(
+                        "DRY_RUN must be true or false: {}",
+                        other
+                    )))
+                }
+            };
+        }
+        if let Some(base_url) = env("LINKS_BASE_URL") {
+            match &mut self.links {
+                Some(links) => links.base_url = base_url,
+                None => self.links = Some(LinksConfig::new(&base_url)),
+            }
+        }
+        Ok(())
+    }
+
+    // Destinations that should receive the result of a validation, falling back to the
+    // success/failure queue when no route matches
+    pub fn destinations(&self, key: &str, report: &ValidationReport) -> Vec<Destination> {
+        let outcome = report.outcome();
+        let default_queue = match outcome {
+            Outcome::Success => &self.success_queue_url,
+            Outcome::Failure => &self.failure_queue_url,
+        };
+        self.routes
+            .destinations(key, outcome, &report.error_codes(), default_queue)
+    }
+
+    // Run the checks of the rule set against the object
+    pub fn validate_file(&self, s3_entity: &S3Entity) -> ValidationReport {
+        self.rules.validate(s3_entity)
+    }
+
+    // Categories of the message of a validation, the static categories when no rule matches
+    pub fn categories(&self, key: &str, report: &ValidationReport) -> Vec<String> {
+        self.category_rules.categories(
+            key,
+            report.outcome(),
+            report.severity(),
+            &report.error_codes(),
+            &self.categories,
+        )
+    }
+
+    // Workflow name of the message of a validation
+    pub fn workflow(&self, key: &str, report: &ValidationReport) -> String {
+        self.category_rules
+            .workflow(
+                key,
+                report.outcome(),
+                report.severity(),
+                &report.error_codes(),
+                &self.workflow,
+            )
+            .to_string()
+    }
+
+    // The configuration of the tenant the object belongs to
+    pub fn for_object(&self, bucket: &str, key: &str) -> Cow<'_, Config> {
+        match &self.tenants {
+            Some(tenants) => self.for_tenant(tenants.resolve(bucket, key)),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    // The configuration of a tenant profile, the configuration itself for an unknown profile
+    pub fn for_tenant(&self, name: &str) -> Cow<'_, Config> {
+        match self
+            .tenants
+            .as_ref()
+            .and_then(|tenants| tenants.profile(name))
+        {
+            Some(profile) => {
+                let mut config = self.with_profile(profile);
+                config.tenant = Some(name.to_string());
+                Cow::Owned(config)
+            }
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    // The configuration with the settings of the profile. It has no tenants, so resolving
+    // the tenant of an object again doesn't apply the profile twice.
+    fn with_profile(&self, profile: &TenantProfile) -> Config {
+        let mut config = self.clone();
+        config.tenants = None;
+        if let Some(rules) = &profile.rules {
+            config.rules = rules.clone();
+        }
+        profile.apply_tags(&mut config.tags);
+        if let Some(url) = &profile.success_queue_url {
+            config.success_queue_url = url.clone();
+        }
+        if let Some(url) = &profile.failure_queue_url {
+            config.failure_queue_url = url.clone();
+        }
+        if let Some(categories) = &profile.categories {
+            config.categories = categories.clone();
+        }
+        if let Some(category_rules) = &profile.category_rules {
+            config.category_rules = category_rules.clone();
+        }
+        if let Some(routes) = &profile.routes {
+            config.routes = routes.clone();
+        }
+        if let Some(shadow) = &profile.shadow {
+            config.shadow = Some(shadow.clone());
+        }
+        config
+    }
+
+    // Only the settings the profile changes are checked, the others are checked once
+    fn check_profile(&self, profile: &TenantProfile) -> Vec<String> {
+        let config = self.with_profile(profile);
+        let mut error_messages = Vec::new();
+        let queue_urls = [
+            ("success_queue_url", &profile.success_queue_url),
+            ("failure_queue_url", &profile.failure_queue_url),
+        ];
+        for (name, url) in queue_urls {
+            if let Some(url) = url {
+                if !url.starts_with("https://") && !url.starts_with("http://") {
+                    error_messages.push(format!("{} is not a valid queue URL: {}", name, url));
+                }
+            }
+        }
+        if profile.categories.is_some() {
+            if config.categories.is_empty() {
+                error_messages.push("At least one category is required".to_string());
+            }
+            if config.categories.iter().any(|c| c.trim().is_empty()) {
+                error_messages.push("Categories must not be empty".to_string());
+            }
+        }
+        if profile.tag_namespace.is_some() {
+            error_messages.extend(config.tags.check());
+        }
+        if profile.rules.is_some() {
+            error_messages.extend(config.rules.check());
+        }
+        if profile.category_rules.is_some() {
+            error_messages.extend(config.category_rules.check());
+        }
+        if profile.routes.is_some() {
+            error_messages.extend(config.routes.check());
+        }
+        if let Some(shadow) = &profile.shadow {
+            error_messages.extend(shadow.check());
+        }
+        // The routes of the config would send the results of the tenant elsewhere whenever
+        // one matches, the tenant has to say which routes it uses
+        let sets_queues =
+            profile.success_queue_url.is_some() || profile.failure_queue_url.is_some();
+        if sets_queues && profile.routes.is_none() && !self.routes.routes.is_empty() {
+            error_messages.push(
+                "A profile that sets its queues must set its routes when the config has routes"
+                    .to_string(),
+            );
+        }
+        error_messages
+    }
+
+    // Objects written by the workflow itself, which must not be validated again
+    pub fn is_internal_object(&self, bucket: &str, key: &str) -> bool {
+        self.claim_check
+            .as_ref()
+            .is_some_and(|claim_check| claim_check.contains(bucket, key))
+            || self
+                .quarantine
+                .as_ref()
+                .is_some_and(|quarantine| quarantine.contains(bucket, key))
+            || self
+                .promotion
+                .as_ref()
+                .is_some_and(|promotion| promotion.contains(bucket, key))
+    }
+
+    // Resolve the settings of a queue, FIFO-ness is detected from the URL unless configured
+    pub fn queue(&self, url: &str) -> Queue {
+        Queue::new(url, self.queues.get(url))
+    }
+
+    // Check every setting and report all the problems at once
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut error_messages = Vec::new();
+
+        if let Some(error) = check_queue_url("SUCCESS_QUEUE_URL", &self.success_queue_url) {
+            error_messages.push(error);
+        }
+        if let Some(error) = check_queue_url("FAILURE_QUEUE_URL", &self.failure_queue_url) {
+            error_messages.push(error);
+        }
+        if self.workflow.trim().is_empty() {
+            error_messages.push("Workflow name must not be empty".to_string());
+        }
+        if self.categories.is_empty() {
+            error_messages.push("At least one category is required".to_string());
+        }
+        if self.categories.iter().any(|c| c.trim().is_empty()) {
+            error_messages.push("Categories must not be empty".to_string());
+        }
+        if !crate::sqs::is_valid_message_id(&self.message_group_id) {
+            error_messages.push(format!(
+                "Message group id must be between 1 and {} printable ASCII characters",
+                MAX_MESSAGE_GROUP_ID_LENGTH
+            ));
+        }
+        error_messages.extend(self.tags.check());
+        error_messages.extend(self.routes.check());
+        error_messages.extend(self.category_rules.check());
+        error_messages.extend(self.rules.check());
+        if let Some(shadow) = &self.shadow {
+            error_messages.extend(shadow.check());
+        }
+        for url in self.queues.keys() {
+            error_messages.extend(self.queue(url).check());
+        }
+        if let Some(claim_check) = &self.claim_check {
+            error_messages.extend(claim_check.check());
+        }
+        if let Some(links) = &self.links {
+            error_messages.extend(links.check());
+        }
+        if let Some(quarantine) = &self.quarantine {
+            error_messages.extend(quarantine.check());
+        }
+        if let Some(promotion) = &self.promotion {
+            error_messages.extend(promotion.check());
+        }
+        if let Some(tenants) = &self.tenants {
+            error_messages.extend(tenants.check());
+            for (name, profile) in &tenants.profiles {
+                error_messages.extend(
+                    self.check_profile(profile)
+                        .into_iter()
+                        .map(|error| format!("Tenant profile {}: {}", name, error)),
+                );
+            }
+        }
+        if self.message_format == MessageFormat::CloudEvents {
+            if self.cloudevents.source.is_empty() {
+                error_messages.push("CloudEvents source must not be empty".to_string());
+            }
+            if self.cloudevents.type_prefix.is_empty() {
+                error_messages.push("CloudEvents type prefix must not be empty".to_string());
+            }
+        }
+
+        if error_messages.is_empty() {
+            return Ok(());
+        }
+        Err(Error::from(format!(
+            "Invalid configuration: {}",
+            error_messages.join(", ")
+        )))
+    }
+}
+
+impl TagNames {
+    fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        let tags = [
+            ("validating", &self.validating),
+            ("validated", &self.validated),
+            ("valid", &self.valid),
+            ("quarantine", &self.quarantine),
+            ("rejected", &self.rejected),
+            ("warnings", &self.warnings),
+            ("tenant", &self.tenant),
+            ("origin", &self.origin),
+        ];
+        for (role, name) in tags.iter() {
+            if name.is_empty() || name.chars().count() > MAX_TAG_KEY_LENGTH {
+                error_messages.push(format!(
+                    "Tag name for {} must be between 1 and {} characters",
+                    role, MAX_TAG_KEY_LENGTH
+                ));
+            }
+        }
+        if self.valid == self.quarantine {
+            error_messages.push("Tag names for valid and quarantine must differ".to_string());
+        }
+        if self.warnings == self.valid || self.warnings == self.quarantine {
+            error_messages
+                .push("Tag name for warnings must differ from valid and quarantine".to_string());
+        }
+        error_messages
+    }
+}
+
+fn check_queue_url(name: &str, url: &str) -> Option<String> {
+    if url.is_empty() {
+        return Some(format!("Missing {} environment variable", name));
+    }
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Some(format!("{} is not a valid queue URL: {}", name, url));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests_config_loading {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_config_from_env_only() {
+        let config = Config::from_sources(
+            None,
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure.fifo"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.success_queue_url, "https://sqs/success.fifo");
+        assert_eq!(config.failure_queue_url, "https://sqs/failure.fifo");
+        assert_eq!(config.workflow, "Validation_Workflow");
+        assert_eq!(config.categories, vec!["CD-TECH", "AM-DEVS"]);
+        assert_eq!(config.tags, TagNames::default());
+    }
+
+    #[test]
+    fn test_config_env_overrides_file() {
+        let file = r#"{
+            "success_queue_url": "https://sqs/file-success.fifo",
+            "failure_queue_url": "https://sqs/file-failure.fifo",
+            "workflow": "File_Workflow",
+            "tags": { "quarantine": "quarantine" }
+        }"#;
+        let config = Config::from_sources(
+            Some(file),
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/env-success.fifo"),
+                ("CATEGORIES", "OPS, DATA"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.success_queue_url, "https://sqs/env-success.fifo");
+        assert_eq!(config.failure_queue_url, "https://sqs/file-failure.fifo");
+        assert_eq!(config.workflow, "File_Workflow");
+        assert_eq!(config.categories, vec!["OPS", "DATA"]);
+        assert_eq!(config.tags.quarantine, "quarantine");
+        assert_eq!(config.tags.valid, "valid");
+    }
+
+    #[test]
+    fn test_failure_delay_requires_standard_queue() {
+        let config = Config::from_sources(
+            None,
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure"),
+                ("FAILURE_QUEUE_DELAY_SECONDS", "120"),
+            ]),
+        )
+        .unwrap();
+        let queue = config.queue("https://sqs/failure");
+        assert!(!queue.fifo);
+        assert_eq!(queue.delay_seconds, Some(120));
+
+        let error = Config::from_sources(
+            None,
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure.fifo"),
+                ("FAILURE_QUEUE_DELAY_SECONDS", "120"),
+            ]),
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("delay_seconds is only supported on standard queues"));
+    }
+
+    #[test]
+    fn test_failure_delay_applies_to_tenant_failure_queues() {
+        let file = r#"{
+            "tenants": {
+                "default_profile": "platform",
+                "profiles": {
+                    "platform": {},
+                    "finance": { "failure_queue_url": "https://sqs/finance-failure" }
+                },
+                "mappings": [{ "prefix": "finance/", "profile": "finance" }]
+            }
+        }"#;
+        let config = Config::from_sources(
+            Some(file),
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure"),
+                ("FAILURE_QUEUE_DELAY_SECONDS", "120"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.queue("https://sqs/failure").delay_seconds, Some(120));
+        let finance = config.for_object("landing", "finance/report.csv");
+        assert_eq!(
+            finance.queue(&finance.failure_queue_url).delay_seconds,
+            Some(120)
+        );
+    }
+
+    #[test]
+    fn test_links_require_a_secret() {
+        let error = Config::from_sources(
+            Some(
+                r#"{ "links": { "base_url": "https://old.example.com/", "secret_env": "TEST_UNSET_LINKS_SECRET" } }"#,
+            ),
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure.fifo"),
+                ("LINKS_BASE_URL", "not a url"),
+            ]),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains(
+            "Invalid links base URL: not a url, Missing links secret in $TEST_UNSET_LINKS_SECRET"
+        ));
+    }
+
+    #[test]
+    fn test_category_rules_from_file() {
+        let file = r#"{
+            "category_rules": [
+                { "prefix": "finance/", "outcome": "failure", "categories": ["FIN-ONCALL"], "workflow": "Finance_Validation" }
+            ]
+        }"#;
+        let config = Config::from_sources(
+            Some(file),
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure.fifo"),
+            ]),
+        )
+        .unwrap();
+        let report = ValidationReport {
+            issues: vec![crate::ValidationIssue::new("empty_file", "File is empty")],
+        };
+        assert_eq!(
+            config.categories("finance/a.txt", &report),
+            vec!["FIN-ONCALL"]
+        );
+        assert_eq!(
+            config.workflow("finance/a.txt", &report),
+            "Finance_Validation"
+        );
+        assert_eq!(
+            config.categories("hr/a.txt", &report),
+            vec!["CD-TECH", "AM-DEVS"]
+        );
+        assert_eq!(config.workflow("hr/a.txt", &report), "Validation_Workflow");
+    }
+
+    #[test]
+    fn test_warning_codes_from_env() {
+        let config = Config::from_sources(
+            Some(r#"{ "rules": { "severities": { "empty_file": "info" } } }"#),
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure.fifo"),
+                ("WARNING_CODES", "invalid_name_format, non_numeric_name"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.rules.severities["empty_file"], Severity::Info);
+        assert_eq!(
+            config.rules.severities["non_numeric_name"],
+            Severity::Warning
+        );
+
+        let error = Config::from_sources(
+            None,
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure.fifo"),
+                ("WARNING_CODES", "empty_files"),
+            ]),
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Unknown error code empty_files in severities"));
+    }
+
+    #[test]
+    fn test_dry_run_from_env() {
+        let env = |dry_run: &'static str| {
+            env_from(&[
+                ("SUCCESS_QUEUE_URL", "https://sqs/success.fifo"),
+                ("FAILURE_QUEUE_URL", "https://sqs/failure.fifo"),
+                ("DRY_RUN", dry_run),
+            ])
+        };
+        assert!(Config::from_sources(None, env("true")).unwrap().dry_run);
+        assert!(
+            !Config::from_sources(Some(r#"{ "dry_run": true }"#), env("0"))
+                .unwrap()
+                .dry_run
+        );
+        let error = Config::from_sources(None, env("yes")).unwrap_err();
+        assert_eq!(error.to_string(), "DRY_RUN must be true or false: yes");
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_fields() {
+        let file = r#"{ "success_queue": "https://sqs/success.fifo" }"#;
+        let error = Config::from_sources(Some(file), env_from(&[])).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid config file"));
+    }
+}
+
+#[cfg(test)]
+mod tests_config_validation {
+    use super::*;
+
+    fn valid_config() -> Config {
+        Config {
+            success_queue_url: "https://sqs/success.fifo".to_string(),
+            failure_queue_url: "https://sqs/failure.fifo".to_string(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_accepts_defaults_with_queues() {
+        assert!(valid_config().validate().is_ok());
+    }
+
+    #[test]
+    fn test_tenant_profile_overrides_the_config() {
+        let tenants: Tenants = serde_json::from_str(
+            r#"{
+                "default_profile": "platform",
+                "profiles": {
+                    "platform": {},
+                    "finance": {
+                        "rules": { "name": "finance", "extensions": ["csv"], "name_parts": null },
+                        "tag_namespace": "finance:",
+                        "failure_queue_url": "https://sqs/finance-failure.fifo",
+                        "categories": ["FIN-OPS"]
+                    }
+                },
+                "mappings": [{ "bucket": "landing", "prefix": "finance/", "profile": "finance" }]
+            }"#,
+        )
+        .unwrap();
+        let config = Config {
+            tenants: Some(tenants),
+            ..valid_config()
+        };
+        assert!(config.validate().is_ok());
+
+        let finance = config.for_object("landing", "finance/report.csv");
+        assert_eq!(finance.rules.name, "finance");
+        assert_eq!(finance.tags.valid, "finance:valid");
+        assert_eq!(finance.success_queue_url, "https://sqs/success.fifo");
+        assert_eq!(
+            finance.failure_queue_url,
+            "https://sqs/finance-failure.fifo"
+        );
+        assert_eq!(finance.categories, vec!["FIN-OPS"]);
+        // The profile is applied once
+        assert_eq!(
+            finance
+                .for_object("landing", "finance/report.csv")
+                .tags
+                .valid,
+            "finance:valid"
+        );
+
+        let platform = config.for_object("other", "finance/report.csv");
+        assert_eq!(platform.rules, RuleSet::default());
+        assert_eq!(platform.tags, TagNames::default());
+        assert_eq!(platform.failure_queue_url, "https://sqs/failure.fifo");
+    }
+
+    #[test]
+    fn test_tenant_profile_routes_and_shadow() {
+        let file = r#"{
+            "success_queue_url": "https://sqs/success.fifo",
+            "failure_queue_url": "https://sqs/failure.fifo",
+            "routes": [{ "prefix": "finance/", "outcome": "failure", "queues": ["https://sqs/finance-triage"] }],
+            "tenants": {
+                "default_profile": "platform",
+                "profiles": {
+                    "platform": {},
+                    "finance": {
+                        "failure_queue_url": "https://sqs/finance-failure.fifo",
+                        "routes": [],
+                        "shadow": { "rules": { "name": "finance-v2" } }
+                    },
+                    "payroll": { "failure_queue_url": "https://sqs/payroll-failure.fifo" }
+                },
+                "mappings": [
+                    { "prefix": "finance/", "profile": "finance" },
+                    { "prefix": "payroll/", "profile": "payroll" }
+                ]
+            }
+        }"#;
+        let error = Config::from_sources(Some(file), |_| None)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "Invalid configuration: Tenant profile payroll: A profile that sets its queues must \
+             set its routes when the config has routes"
+        );
+
+        let config: Config = serde_json::from_str(&file.replace(
+            r#""payroll": { "failure_queue_url": "https://sqs/payroll-failure.fifo" }"#,
+            r#""payroll": { "failure_queue_url": "https://sqs/payroll-failure.fifo", "routes": [] }"#,
+        ))
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let finance = config.for_object("landing", "finance/a.txt");
+        let report = ValidationReport {
+            issues: vec![crate::ValidationIssue::new("empty_file", "Empty")],
+        };
+        assert_eq!(
+            finance.destinations("finance/a.txt", &report),
+            vec![Destination::sqs("https://sqs/finance-failure.fifo")]
+        );
+        assert_eq!(finance.shadow.as_ref().unwrap().rules.name, "finance-v2");
+        assert!(config.for_object("landing", "hr/a.txt").shadow.is_none());
+    }
+
+    #[test]
+    fn test_validate_reports_tenant_profile_problems() {
+        let tenants: Tenants = serde_json::from_str(
+            r#"{
+                "default_profile": "platform",
+                "profiles": {
+                    "finance": {
+                        "rules": { "extensions": ["."] },
+                        "success_queue_url": "sqs/finance-success",
+                        "categories": []
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let config = Config {
+            tenants: Some(tenants),
+            ..valid_config()
+        };
+        let error = config.validate().unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "Invalid configuration: Unknown default tenant profile platform, \
+             Tenant profile finance: success_queue_url is not a valid queue URL: sqs/finance-success, \
+             Tenant profile finance: At least one category is required, \
+             Tenant profile finance: Extensions must not be empty"
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let config = Config {
+            failure_queue_url: "sqs/failure".to_string(),
+            success_queue_url: String::new(),
+            categories: vec![],
+            ..valid_config()
+        };
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("Missing SUCCESS_QUEUE_URL environment variable"));
+        assert!(error.contains("FAILURE_QUEUE_URL is not a valid queue URL"));
+        assert!(error.contains("At least one category is required"));
+    }
+
+    #[test]
+    fn test_validate_rejects_clashing_tag_names() {
+        let mut config = valid_config();
+        config.tags.quarantine = "valid".to_string();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("Tag names for valid and quarantine must differ"));
+    }
+}
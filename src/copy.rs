@@ -0,0 +1,264 @@
+use crate::message::{ObjectIdentification, ObjectLocation};
+use crate::store::ObjectStore;
+use aws_sdk_s3::model::{
+    CompletedMultipartUpload, CompletedPart, MetadataDirective, TaggingDirective,
+};
+use aws_sdk_s3::Client as S3Client;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use lambda_runtime::Error;
+
+// CopyObject accepts sources of up to 5 GiB, larger objects are copied part by part
+pub const MAX_COPY_OBJECT_BYTES: i64 = 5 * 1024 * 1024 * 1024;
+const MIN_PART_BYTES: i64 = 512 * 1024 * 1024;
+const MAX_PARTS: i64 = 10_000;
+// Parts copied at the same time
+const PART_CONCURRENCY: usize = 8;
+
+// URL encode a key, keeping the slashes when it is used as a path
+fn encode(value: &str, keep_slashes: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slashes => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// The x-amz-copy-source of an object version: the key is URL encoded, the slashes are kept
+pub fn copy_source(bucket: &str, key: &str, version_id: Option<&str>) -> String {
+    match version_id {
+        Some(version_id) => format!("{}/{}?versionId={}", bucket, encode(key, true), version_id),
+        None => format!("{}/{}", bucket, encode(key, true)),
+    }
+}
+
+// Byte ranges of the parts of a multipart copy, as (part number, first byte, last byte)
+fn part_ranges(size: i64) -> Vec<(i32, i64, i64)> {
+    let part_size = MIN_PART_BYTES.max((size + MAX_PARTS - 1) / MAX_PARTS);
+    (0..size)
+        .step_by(part_size as usize)
+        .enumerate()
+        .map(|(index, start)| (index as i32 + 1, start, (start + part_size).min(size) - 1))
+        .collect()
+}
+
+// Copy the exact version of an object with its metadata and tags
+pub async fn copy_version(
+    store: &dyn ObjectStore,
+    source: &ObjectIdentification,
+    bucket: &str,
+    key: &str,
+) -> Result<ObjectLocation, Error> {
+    store
+        .copy_object(source, bucket, key)
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+                "Original Error: {}; Could not copy Object s3://{}/{} versionId: {} to s3://{}/{}",
+                original_error,
+                source.bucket,
+                source.key,
+                source.version_id.as_deref().unwrap_or("null"),
+                bucket,
+                key
+            ))
+        })
+}
+
+// The copy of S3Store: CopyObject, or a multipart copy for objects it doesn't accept
+pub(crate) async fn copy_object_version(
+    s3_client: &S3Client,
+    source: &ObjectIdentification,
+    bucket: &str,
+    key: &str,
+) -> Result<ObjectLocation, Error> {
+    match source.size {
+        Some(size) if size > MAX_COPY_OBJECT_BYTES => {
+            multipart_copy(s3_client, source, size, bucket, key).await
+        }
+        _ => {
+            let output = s3_client
+                .copy_object()
+                .copy_source(copy_source(
+                    &source.bucket,
+                    &source.key,
+                    source.version_id.as_deref(),
+                ))
+                .bucket(bucket)
+                .key(key)
+                .metadata_directive(MetadataDirective::Copy)
+                .tagging_directive(TaggingDirective::Copy)
+                .send()
+                .await
+                .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+            Ok(ObjectLocation {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                version_id: output.version_id().map(str::to_string),
+            })
+        }
+    }
+}
+
+// UploadPartCopy doesn't carry the metadata and tags over, they are set when the upload is created
+async fn multipart_copy(
+    s3_client: &S3Client,
+    source: &ObjectIdentification,
+    size: i64,
+    bucket: &str,
+    key: &str,
+) -> Result<ObjectLocation, Error> {
+    let head = s3_client
+        .head_object()
+        .bucket(&source.bucket)
+        .key(&source.key)
+        .set_version_id(source.version_id.clone())
+        .send()
+        .await
+        .map_err(|e| e.into_service_error().to_string())?;
+    let tagging = s3_client
+        .get_object_tagging()
+        .bucket(&source.bucket)
+        .key(&source.key)
+        .set_version_id(source.version_id.clone())
+        .send()
+        .await
+        .map_err(|e| e.into_service_error().to_string())?
+        .tag_set()
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| {
+            format!(
+                "{}={}",
+                encode(tag.key().unwrap_or_default(), false),
+                encode(tag.value().unwrap_or_default(), false)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("&");
+
+    let upload = s3_client
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .set_content_type(head.content_type().map(str::to_string))
+        .set_content_encoding(head.content_encoding().map(str::to_string))
+        .set_content_disposition(head.content_disposition().map(str::to_string))
+        .set_content_language(head.content_language().map(str::to_string))
+        .set_cache_control(head.cache_control().map(str::to_string))
+        .set_metadata(head.metadata().cloned())
+        .tagging(tagging)
+        .send()
+        .await
+        .map_err(|e| e.into_service_error().to_string())?;
+    let upload_id = upload.upload_id().ok_or("Missing upload id")?.to_string();
+
+    let source_header = copy_source(&source.bucket, &source.key, source.version_id.as_deref());
+    let parts: Result<Vec<CompletedPart>, Error> = stream::iter(part_ranges(size))
+        .map(|(part_number, first, last)| {
+            let request = s3_client
+                .upload_part_copy()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .part_number(part_number)
+                .copy_source(&source_header)
+                .copy_source_range(format!("bytes={}-{}", first, last));
+            async move {
+                let output = request
+                    .send()
+                    .await
+                    .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+                let e_tag = output
+                    .copy_part_result()
+                    .and_then(|result| result.e_tag())
+                    .ok_or("Missing part ETag")?;
+                Ok(CompletedPart::builder()
+                    .part_number(part_number)
+                    .e_tag(e_tag)
+                    .build())
+            }
+        })
+        .buffered(PART_CONCURRENCY)
+        .try_collect()
+        .await;
+
+    let completed = match parts {
+        Ok(parts) => s3_client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|e| Error::from(e.into_service_error().to_string())),
+        Err(e) => Err(e),
+    };
+    match completed {
+        Ok(output) => Ok(ObjectLocation {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            version_id: output.version_id().map(str::to_string),
+        }),
+        Err(e) => {
+            // Don't leave the copied parts behind, they are billed until the upload is aborted
+            let _ = s3_client
+                .abort_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .send()
+                .await;
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_copy_source {
+    use super::*;
+
+    #[test]
+    fn test_copy_source_encodes_the_key() {
+        assert_eq!(
+            copy_source("landing", "finance/1234 5678+é.txt", Some("v1")),
+            "landing/finance/1234%205678%2B%C3%A9.txt?versionId=v1"
+        );
+        assert_eq!(
+            copy_source("landing", "1234-5678.txt", None),
+            "landing/1234-5678.txt"
+        );
+        assert_eq!(encode("a/b=c&d", false), "a%2Fb%3Dc%26d");
+    }
+
+    #[test]
+    fn test_part_ranges_cover_the_object() {
+        let size = MAX_COPY_OBJECT_BYTES + 1;
+        let ranges = part_ranges(size);
+        assert_eq!(ranges.len(), 11);
+        assert_eq!(ranges[0], (1, 0, MIN_PART_BYTES - 1));
+        assert_eq!(ranges[10], (11, size - 1, size - 1));
+        for window in ranges.windows(2) {
+            assert_eq!(window[0].2 + 1, window[1].1);
+        }
+    }
+
+    #[test]
+    fn test_part_ranges_stay_under_the_part_limit() {
+        // 6 TB would need more than 10000 parts of 512 MiB
+        let size: i64 = 6 * 1000 * 1000 * 1000 * 1000;
+        let ranges = part_ranges(size);
+        assert!(ranges.len() as i64 <= MAX_PARTS);
+        assert_eq!(ranges.last().unwrap().2, size - 1);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::config::Config;
+use crate::message::{MessageTag, ObjectIdentification, ObjectLocation};
+use crate::sink::{Delivery, NotificationSink};
+use crate::sqs::OutboundMessage;
+use crate::store::{ObjectHead, ObjectPage, ObjectStore};
+use async_trait::async_trait;
+use aws_sdk_s3::model::Tagging;
+use lambda_runtime::Error;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+// Message id reported for the messages that were only logged
+pub const DRY_RUN_MESSAGE_ID: &str = "dry-run";
+
+// Tag sets remembered for the reads that follow the writes, an object is tagged within one
+// invocation so the oldest can be forgotten
+const MAX_REMEMBERED_TAGGINGS: usize = 1000;
+
+type ObjectVersion = (String, String, Option<String>);
+
+// The tag sets and the order they were first written in, the oldest is forgotten first so the
+// objects of the batch being validated are kept
+#[derive(Default)]
+struct RememberedTaggings {
+    taggings: HashMap<ObjectVersion, Tagging>,
+    order: VecDeque<ObjectVersion>,
+}
+
+impl RememberedTaggings {
+    fn get(&self, object_version: &ObjectVersion) -> Option<&Tagging> {
+        self.taggings.get(object_version)
+    }
+
+    fn insert(&mut self, object_version: ObjectVersion, tagging: Tagging) {
+        if self
+            .taggings
+            .insert(object_version.clone(), tagging)
+            .is_none()
+        {
+            self.order.push_back(object_version);
+        }
+        while self.order.len() > MAX_REMEMBERED_TAGGINGS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.taggings.remove(&oldest);
+            }
+        }
+    }
+}
+
+// An ObjectStore that reads from the inner store and logs the writes instead of making them,
+// so a rule set can be tried against production traffic. Every log line carries dry_run=true
+// and the operation, the objects and tags it would have written as fields. The tags it would
+// have written are read back, so the tag sets built on top of them are the ones of a real run.
+pub struct DryRunStore {
+    inner: Arc<dyn ObjectStore>,
+    taggings: Mutex<RememberedTaggings>,
+}
+
+impl DryRunStore {
+    pub fn new(inner: Arc<dyn ObjectStore>) -> DryRunStore {
+        DryRunStore {
+            inner,
+            taggings: Mutex::new(RememberedTaggings::default()),
+        }
+    }
+
+    fn object_version(bucket: &str, key: &str, version_id: Option<&str>) -> ObjectVersion {
+        (
+            bucket.to_string(),
+            key.to_string(),
+            version_id.map(str::to_string),
+        )
+    }
+}
+
+// The store the workflow should use, the writes are only logged in dry-run mode
+pub fn object_store(store: Arc<dyn ObjectStore>, config: &Config) -> Arc<dyn ObjectStore> {
+    if config.dry_run {
+        Arc::new(DryRunStore::new(store))
+    } else {
+        store
+    }
+}
+
+fn tags_field(tagging: &Tagging) -> String {
+    serde_json::to_string(&MessageTag::from_tagging(tagging)).unwrap_or_default()
+}
+
+#[async_trait]
+impl ObjectStore for DryRunStore {
+    async fn get_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<Tagging, Error> {
+        let remembered = self
+            .taggings
+            .lock()
+            .unwrap()
+            .get(&DryRunStore::object_version(bucket, key, version_id))
+            .cloned();
+        match remembered {
+            Some(tagging) => Ok(tagging),
+            None => self.inner.get_object_tagging(bucket, key, version_id).await,
+        }
+    }
+
+    async fn put_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        tagging: Tagging,
+    ) -> Result<(), Error> {
+        info!(
+            dry_run = true,
+            operation = "put_object_tagging",
+            bucket,
+            key,
+            version_id = version_id.unwrap_or("null"),
+            tags = %tags_field(&tagging),
+            "Dry run: would tag the object"
+        );
+        self.taggings.lock().unwrap().insert(
+            DryRunStore::object_version(bucket, key, version_id),
+            tagging,
+        );
+        Ok(())
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<ObjectHead, Error> {
+        self.inner.head_object(bucket, key, version_id).await
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<Vec<u8>, Error> {
+        self.inner.get_object(bucket, key).await
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<(), Error> {
+        info!(
+            dry_run = true,
+            operation = "put_object",
+            bucket,
+            key,
+            content_type,
+            size = body.len(),
+            "Dry run: would write the object"
+        );
+        Ok(())
+    }
+
+    async fn copy_object(
+        &self,
+        source: &ObjectIdentification,
+        bucket: &str,
+        key: &str,
+    ) -> Result<ObjectLocation, Error> {
+        info!(
+            dry_run = true,
+            operation = "copy_object",
+            source_bucket = %source.bucket,
+            source_key = %source.key,
+            source_version_id = source.version_id.as_deref().unwrap_or("null"),
+            bucket,
+            key,
+            "Dry run: would copy the object"
+        );
+        // No copy exists, so it has no version. It would have the tags of the source, they are
+        // remembered for the workflow that tags the copy next.
+        let tagging = self
+            .get_object_tagging(&source.bucket, &source.key, source.version_id.as_deref())
+            .await?;
+        self.taggings
+            .lock()
+            .unwrap()
+            .insert(DryRunStore::object_version(bucket, key, None), tagging);
+        Ok(ObjectLocation {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            version_id: None,
+        })
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<(), Error> {
+        info!(
+            dry_run = true,
+            operation = "delete_object",
+            bucket,
+            key,
+            version_id = version_id.unwrap_or("null"),
+            "Dry run: would delete the object"
+        );
+        Ok(())
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        all_versions: bool,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+    ) -> Result<ObjectPage, Error> {
+        self.inner
+            .list_objects(bucket, prefix, all_versions, key_marker, version_id_marker)
+            .await
+    }
+}
+
+// Logs the messages it is given instead of delivering them, used for every destination
+// in dry-run mode
+pub struct DryRunSink {
+    pub destination: String,
+}
+
+#[async_trait]
+impl NotificationSink for DryRunSink {
+    async fn send_batch(&self, messages: &[&OutboundMessage]) -> Vec<Delivery> {
+        for message in messages {
+            info!(
+                dry_run = true,
+                operation = "send_message",
+                destination = %self.destination,
+                group_id = %message.group_id,
+                deduplication_id = %message.deduplication_id,
+                attributes = %serde_json::to_string(&message.attributes).unwrap_or_default(),
+                body = %message.body,
+                "Dry run: would send the message"
+            );
+        }
+        vec![Delivery::Sent(DRY_RUN_MESSAGE_ID.to_string()); messages.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests_dry_run {
+    use super::*;
+    use crate::handler::function_handler;
+    use crate::memory::{MemoryMessageQueue, MemoryObjectStore, StoreOperation};
+    use crate::quarantine::QuarantineConfig;
+    use crate::sink::Notifier;
+    use crate::testing::{assert_object_tags, assert_tags, S3EventBuilder, S3RecordBuilder};
+    use crate::Outcome;
+
+    fn config() -> Config {
+        Config {
+            success_queue_url: "https://sqs.eu-west-1.amazonaws.com/123456789012/success"
+                .to_string(),
+            failure_queue_url: "https://sqs.eu-west-1.amazonaws.com/123456789012/failure"
+                .to_string(),
+            quarantine: Some(serde_json::from_str::<QuarantineConfig>("{}").unwrap()),
+            dry_run: true,
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_validates_without_writing() {
+        let memory = Arc::new(MemoryObjectStore::new());
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let version_id = memory.insert("landing", "report.csv", b"data");
+        memory.take_calls();
+        let config = config();
+        let store = object_store(memory.clone(), &config);
+        let notifier = Notifier::new(&aws_config::SdkConfig::builder().build())
+            .with_message_queue(queue.clone());
+        let event = S3EventBuilder::new()
+            .record(
+                S3RecordBuilder::new("landing", "report.csv")
+                    .size(4)
+                    .version_id(&version_id),
+            )
+            .lambda_event("request-1");
+
+        let response = function_handler(event, store.as_ref(), &notifier, &config)
+            .await
+            .unwrap();
+        // The verdict is the same as without dry run
+        let record = &response.records[0];
+        assert_eq!(record.outcome, Some(Outcome::Failure));
+        assert_eq!(
+            record.notifications[0].message_id.as_deref(),
+            Some(DRY_RUN_MESSAGE_ID)
+        );
+        // The tags are read back as if they had been written
+        assert_tags(
+            &store
+                .get_object_tagging("landing", "report.csv", Some(&version_id))
+                .await
+                .unwrap(),
+            &[("validated", "true"), ("quarentine", "true")],
+        );
+
+        // Only reads reached the store, the object is neither tagged nor moved
+        assert!(memory.calls().iter().all(|call| matches!(
+            call.operation,
+            StoreOperation::GetObjectTagging | StoreOperation::HeadObject
+        )));
+        assert_object_tags(&memory, "landing", "report.csv", &[]);
+        assert!(memory
+            .object("landing", "quarantine/report.csv", None)
+            .is_none());
+        assert!(queue.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_quarantines_an_object_of_a_tenant() {
+        let memory = Arc::new(MemoryObjectStore::new());
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let version_id = memory.insert("landing", "finance/report.csv", b"data");
+        let config = Config {
+            tenants: Some(
+                serde_json::from_str(
+                    r#"{
+                        "default_profile": "platform",
+                        "profiles": { "platform": {}, "finance": {} },
+                        "mappings": [{ "prefix": "finance/", "profile": "finance" }]
+                    }"#,
+                )
+                .unwrap(),
+            ),
+            ..config()
+        };
+        let store = object_store(memory.clone(), &config);
+        let notifier = Notifier::new(&aws_config::SdkConfig::builder().build())
+            .with_message_queue(queue.clone());
+        let event = S3EventBuilder::new()
+            .record(
+                S3RecordBuilder::new("landing", "finance/report.csv")
+                    .size(4)
+                    .version_id(&version_id),
+            )
+            .lambda_event("request-1");
+
+        let response = function_handler(event, store.as_ref(), &notifier, &config)
+            .await
+            .unwrap();
+        assert_eq!(response.records[0].outcome, Some(Outcome::Failure));
+        // The copy that would have been made is tagged with the tenant, nothing was written
+        assert_tags(
+            &store
+                .get_object_tagging("landing", "quarantine/finance/report.csv", None)
+                .await
+                .unwrap(),
+            &[
+                ("validated", "true"),
+                ("quarentine", "true"),
+                ("validation:tenant", "finance"),
+            ],
+        );
+        assert!(memory
+            .object("landing", "quarantine/finance/report.csv", None)
+            .is_none());
+        assert_object_tags(&memory, "landing", "finance/report.csv", &[]);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_oldest_taggings_are_forgotten() {
+        let memory = Arc::new(MemoryObjectStore::new());
+        let store = DryRunStore::new(memory.clone());
+        let keys: Vec<String> = (0..=MAX_REMEMBERED_TAGGINGS)
+            .map(|index| format!("{}.txt", index))
+            .collect();
+        for key in &keys {
+            memory.insert("landing", key, b"data");
+            store
+                .put_object_tagging("landing", key, None, crate::tagging!("validated" => "true"))
+                .await
+                .unwrap();
+        }
+        // The first object was forgotten and is read from the store, the others are kept
+        let first = store
+            .get_object_tagging("landing", &keys[0], None)
+            .await
+            .unwrap();
+        assert_tags(&first, &[]);
+        for key in &keys[1..] {
+            let tagging = store
+                .get_object_tagging("landing", key, None)
+                .await
+                .unwrap();
+            assert_tags(&tagging, &[("validated", "true")]);
+        }
+    }
+}
@@ -133,22 +133,13 @@ where
 #[cfg(test)]
 mod tests_single_tag_pattern {
     use super::*;
+    use crate::tagging;
 
     #[test]
     fn test_single_tag_methods_for_tagging() {
         let input: &str = "only_tag";
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("only_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("only_tag")
-                .value("false")
-                .build()]))
-            .build();
+        let expected_true_output = tagging!["only_tag" => "true"];
+        let expected_false_output = tagging!["only_tag" => "false"];
         assert_eq!(Tagging::tag_as_true(input), expected_true_output);
         assert_eq!(Tagging::tag_as_false(input), expected_false_output);
     }
@@ -156,18 +147,8 @@ mod tests_single_tag_pattern {
     #[test]
     fn test_single_tag_methods_for_get_object_tagging_output() {
         let input: &str = "only_tag";
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("only_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("only_tag")
-                .value("false")
-                .build()]))
-            .build();
+        let expected_true_output = tagging!["only_tag" => "true"];
+        let expected_false_output = tagging!["only_tag" => "false"];
         assert_eq!(
             GetObjectTaggingOutput::tag_as_true(input),
             expected_true_output
@@ -182,93 +163,45 @@ mod tests_single_tag_pattern {
 #[cfg(test)]
 mod tests_append_tag_pattern {
     use super::*;
+    use crate::tagging;
+    use crate::testing::tagging_output;
 
     #[test]
     fn test_append_tag_methods_for_tagging() {
-        let initial_state = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
+        let initial_state = tagging!["initial_tag" => "true"];
         let input: &str = "new_tag";
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("initial_tag").value("true").build(),
-                Tag::builder().key("new_tag").value("true").build(),
-            ]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("initial_tag").value("true").build(),
-                Tag::builder().key("new_tag").value("false").build(),
-            ]))
-            .build();
+        let expected_true_output = tagging!["initial_tag" => "true", "new_tag" => "true"];
+        let expected_false_output = tagging!["initial_tag" => "true", "new_tag" => "false"];
         assert_eq!(initial_state.add_true_tag(input), expected_true_output);
         assert_eq!(initial_state.add_false_tag(input), expected_false_output);
     }
 
     #[test]
     fn test_append_tag_methods_for_tagging_from_empty() {
-        let initial_state = Tagging::builder().build();
+        let initial_state = tagging![];
         let input: &str = "new_tag";
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("new_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("new_tag")
-                .value("false")
-                .build()]))
-            .build();
+        let expected_true_output = tagging!["new_tag" => "true"];
+        let expected_false_output = tagging!["new_tag" => "false"];
         assert_eq!(initial_state.add_true_tag(input), expected_true_output);
         assert_eq!(initial_state.add_false_tag(input), expected_false_output);
     }
 
     #[test]
     fn test_append_tag_methods_for_get_object_tagging_output() {
-        let initial_state = GetObjectTaggingOutput::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
+        let initial_state = tagging_output(&[("initial_tag", "true")]);
         let input: &str = "new_tag";
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("initial_tag").value("true").build(),
-                Tag::builder().key("new_tag").value("true").build(),
-            ]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("initial_tag").value("true").build(),
-                Tag::builder().key("new_tag").value("false").build(),
-            ]))
-            .build();
+        let expected_true_output = tagging!["initial_tag" => "true", "new_tag" => "true"];
+        let expected_false_output = tagging!["initial_tag" => "true", "new_tag" => "false"];
         assert_eq!(initial_state.add_true_tag(input), expected_true_output);
         assert_eq!(initial_state.add_false_tag(input), expected_false_output);
     }
 
     #[test]
     fn test_append_tag_methods_for_get_object_tagging_output_from_empty() {
-        let initial_state = GetObjectTaggingOutput::builder().build();
+        let initial_state = tagging_output(&[]);
         let input: &str = "new_tag";
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("new_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("new_tag")
-                .value("false")
-                .build()]))
-            .build();
+        let expected_true_output = tagging!["new_tag" => "true"];
+        let expected_false_output = tagging!["new_tag" => "false"];
         assert_eq!(initial_state.add_true_tag(input), expected_true_output);
         assert_eq!(initial_state.add_false_tag(input), expected_false_output);
     }
@@ -277,27 +210,14 @@ mod tests_append_tag_pattern {
 #[cfg(test)]
 mod tests_replace_tag_pattern {
     use super::*;
+    use crate::tagging;
+    use crate::testing::tagging_output;
 
     #[test]
     fn test_replace_tag_methods_for_tagging() {
-        let initial_state = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("new_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("new_tag")
-                .value("false")
-                .build()]))
-            .build();
+        let initial_state = tagging!["initial_tag" => "true"];
+        let expected_true_output = tagging!["new_tag" => "true"];
+        let expected_false_output = tagging!["new_tag" => "false"];
         assert_eq!(
             initial_state.replace_with_true_tag("initial_tag", "new_tag"),
             expected_true_output
@@ -310,24 +230,9 @@ mod tests_replace_tag_pattern {
 
     #[test]
     fn test_replace_tag_methods_for_tagging_no_matches() {
-        let initial_state = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
+        let initial_state = tagging!["initial_tag" => "true"];
+        let expected_true_output = tagging!["initial_tag" => "true"];
+        let expected_false_output = tagging!["initial_tag" => "true"];
         assert_eq!(
             initial_state.replace_with_true_tag("non_existent_tag", "new_tag"),
             expected_true_output
@@ -340,25 +245,10 @@ mod tests_replace_tag_pattern {
 
     #[test]
     fn test_replace_tag_methods_for_tagging_multiple_matches() {
-        let initial_state = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("initial_tag").value("true").build(),
-                Tag::builder().key("initial_tag").value("false").build(),
-                Tag::builder().key("secondary_tag").value("true").build(),
-            ]))
-            .build();
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("secondary_tag").value("true").build(),
-                Tag::builder().key("new_tag").value("true").build(),
-            ]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("secondary_tag").value("true").build(),
-                Tag::builder().key("new_tag").value("false").build(),
-            ]))
-            .build();
+        let initial_state =
+            tagging!["initial_tag" => "true", "initial_tag" => "false", "secondary_tag" => "true"];
+        let expected_true_output = tagging!["secondary_tag" => "true", "new_tag" => "true"];
+        let expected_false_output = tagging!["secondary_tag" => "true", "new_tag" => "false"];
         assert_eq!(
             initial_state.replace_with_true_tag("initial_tag", "new_tag"),
             expected_true_output
@@ -371,24 +261,9 @@ mod tests_replace_tag_pattern {
 
     #[test]
     fn test_replace_tag_methods_for_get_object_tagging_output() {
-        let initial_state = GetObjectTaggingOutput::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("new_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("new_tag")
-                .value("false")
-                .build()]))
-            .build();
+        let initial_state = tagging_output(&[("initial_tag", "true")]);
+        let expected_true_output = tagging!["new_tag" => "true"];
+        let expected_false_output = tagging!["new_tag" => "false"];
         assert_eq!(
             initial_state.replace_with_true_tag("initial_tag", "new_tag"),
             expected_true_output
@@ -401,24 +276,9 @@ mod tests_replace_tag_pattern {
 
     #[test]
     fn test_replace_tag_methods_for_get_object_tagging_output_no_matches() {
-        let initial_state = GetObjectTaggingOutput::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("initial_tag")
-                .value("true")
-                .build()]))
-            .build();
+        let initial_state = tagging_output(&[("initial_tag", "true")]);
+        let expected_true_output = tagging!["initial_tag" => "true"];
+        let expected_false_output = tagging!["initial_tag" => "true"];
         assert_eq!(
             initial_state.replace_with_true_tag("non_existent_tag", "new_tag"),
             expected_true_output
@@ -431,25 +291,13 @@ mod tests_replace_tag_pattern {
 
     #[test]
     fn test_replace_tag_methods_for_get_object_tagging_output_multiple_matches() {
-        let initial_state = GetObjectTaggingOutput::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("initial_tag").value("true").build(),
-                Tag::builder().key("initial_tag").value("false").build(),
-                Tag::builder().key("secondary_tag").value("true").build(),
-            ]))
-            .build();
-        let expected_true_output = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("secondary_tag").value("true").build(),
-                Tag::builder().key("new_tag").value("true").build(),
-            ]))
-            .build();
-        let expected_false_output = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("secondary_tag").value("true").build(),
-                Tag::builder().key("new_tag").value("false").build(),
-            ]))
-            .build();
+        let initial_state = tagging_output(&[
+            ("initial_tag", "true"),
+            ("initial_tag", "false"),
+            ("secondary_tag", "true"),
+        ]);
+        let expected_true_output = tagging!["secondary_tag" => "true", "new_tag" => "true"];
+        let expected_false_output = tagging!["secondary_tag" => "true", "new_tag" => "false"];
         assert_eq!(
             initial_state.replace_with_true_tag("initial_tag", "new_tag"),
             expected_true_output
@@ -464,33 +312,20 @@ mod tests_replace_tag_pattern {
 #[cfg(test)]
 mod tests_remove_tag_pattern {
     use super::*;
+    use crate::tagging;
+    use crate::testing::tagging_output;
 
     #[test]
     fn test_remove_tag_method_for_for_tagging() {
-        let initial_state = Tagging::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("initial_tag").value("true").build(),
-                Tag::builder().key("secondary_tag").value("true").build(),
-            ]))
-            .build();
-        let expected_output_keep = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("secondary_tag")
-                .value("true")
-                .build()]))
-            .build();
+        let initial_state = tagging!["initial_tag" => "true", "secondary_tag" => "true"];
+        let expected_output_keep = tagging!["secondary_tag" => "true"];
         assert_eq!(
             initial_state.remove_tag("initial_tag"),
             expected_output_keep
         );
 
-        let secondary_state = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("secondary_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_output_empty = Tagging::builder().build();
+        let secondary_state = tagging!["secondary_tag" => "true"];
+        let expected_output_empty = tagging![];
         assert_eq!(
             secondary_state.remove_tag("secondary_tag"),
             expected_output_empty
@@ -499,30 +334,15 @@ mod tests_remove_tag_pattern {
 
     #[test]
     fn test_remove_tag_method_for_get_object_tagging_output() {
-        let initial_state = GetObjectTaggingOutput::builder()
-            .set_tag_set(Some(vec![
-                Tag::builder().key("initial_tag").value("true").build(),
-                Tag::builder().key("secondary_tag").value("true").build(),
-            ]))
-            .build();
-        let expected_output_keep = Tagging::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("secondary_tag")
-                .value("true")
-                .build()]))
-            .build();
+        let initial_state = tagging_output(&[("initial_tag", "true"), ("secondary_tag", "true")]);
+        let expected_output_keep = tagging!["secondary_tag" => "true"];
         assert_eq!(
             initial_state.remove_tag("initial_tag"),
             expected_output_keep
         );
 
-        let secondary_state = GetObjectTaggingOutput::builder()
-            .set_tag_set(Some(vec![Tag::builder()
-                .key("secondary_tag")
-                .value("true")
-                .build()]))
-            .build();
-        let expected_output_empty = Tagging::builder().build();
+        let secondary_state = tagging_output(&[("secondary_tag", "true")]);
+        let expected_output_empty = tagging![];
         assert_eq!(
             secondary_state.remove_tag("secondary_tag"),
             expected_output_empty
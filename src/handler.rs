@@ -0,0 +1,713 @@
+use crate::batch::{EntryResult, MessageBuffer};
+use crate::claim_check::offload_if_oversized;
+use crate::config::{Config, MessageFormat};
+use crate::message::{
+    encode_message, MessageTag, ObjectIdentification, ValidationMessageBody, SCHEMA_VERSION,
+};
+use crate::promotion::promote_object;
+use crate::shadow::{DivergenceRecord, Verdict};
+use crate::sink::{Destination, Notifier};
+use crate::sqs::{deduplication_id, OutboundMessage};
+use crate::store::ObjectStore;
+use crate::workflow::{get_tags, quarantine_links, ObjectState};
+use crate::{add_tag, encode_key, single_tag, Outcome, Severity};
+use aws_lambda_events::event::s3::{
+    S3Entity, S3Event, S3EventRecord, S3RequestParameters, S3UserIdentity,
+};
+use lambda_runtime::{Context, Error, LambdaEvent};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+
+// Define a struct to represent the response of the function
+#[derive(Serialize, Debug)]
+pub struct Response {
+    pub req_id: String,
+    pub message: String,
+    pub records: Vec<RecordResult>,
+}
+
+// Result of sending the notification of a record to one destination
+#[derive(Serialize, Debug)]
+pub struct NotificationResult {
+    pub destination: String,
+    pub message_id: Option<String>,
+    pub error: Option<String>,
+}
+
+impl From<EntryResult> for NotificationResult {
+    fn from(entry: EntryResult) -> Self {
+        match entry.result {
+            Ok(message_id) => NotificationResult {
+                destination: entry.destination,
+                message_id: Some(message_id),
+                error: None,
+            },
+            Err(e) => NotificationResult {
+                destination: entry.destination,
+                message_id: None,
+                error: Some(e),
+            },
+        }
+    }
+}
+
+// Result of processing one record of the event
+#[derive(Serialize, Debug)]
+pub struct RecordResult {
+    pub key: Option<String>,
+    pub version_id: Option<String>,
+    pub outcome: Option<Outcome>,
+    pub message: String,
+    pub notifications: Vec<NotificationResult>,
+}
+
+// Tag and validate the object of a record, the notifications are added to the buffer
+// and sent for all the records at once. No message is built without a buffer.
+async fn validate_record(
+    record: &S3EventRecord,
+    record_index: usize,
+    context: &Context,
+    store: &dyn ObjectStore,
+    config: &Config,
+    buffer: Option<&mut MessageBuffer>,
+) -> Result<RecordResult, Error> {
+    // Because the S3 bucket is using versioning, we need the file key and version number
+    // to operate on the correct file. We can get this information from the S3Object struct in the
+    // event payload and validate it.
+    let event_s3_attributes = &record.s3;
+    // Identification of the object version sent along with the result
+    let object = ObjectIdentification::from_record(record)?;
+    // The profile of the tenant of the object decides the rules, tags, queues and categories
+    let tenant_config = config.for_object(&object.bucket, &object.key);
+    let config: &Config = &tenant_config;
+
+    // A file released from quarantine is copied back with its valid tag, it is not validated
+    // again as the rules would quarantine it once more
+    if record.event_name.as_deref() == Some("ObjectCreated:Copy") {
+        let tags = get_tags(store, &object).await?;
+        if ObjectState::from_tags(config, &tags) == ObjectState::Valid {
+            info!(
+                "Skipping s3://{}/{} released from quarantine",
+                object.bucket, object.key
+            );
+            return Ok(RecordResult {
+                key: Some(object.key),
+                version_id: object.version_id,
+                outcome: None,
+                message: "Skipped file released from quarantine".to_string(),
+                notifications: Vec::new(),
+            });
+        }
+    }
+
+    //Add a tag "validating" to the file in order to allow for observability from outside the bucket.
+    single_tag(event_s3_attributes, store, &config.tags.validating).await?;
+
+    // Start by validating the file using the object attributes from the event payload.
+
+    // Check if the file type is .txt for tests
+    // Check if the file is not zero bytes
+    // Check if the file name without the extension is conformant with a particular code
+    // The severities of the config decide which failed checks only warn
+    let report = config.validate_file(event_s3_attributes);
+    let validation_message = report.message();
+    for issue in report.issues_with(Severity::Info) {
+        info!(
+            "Check {} failed with severity info: {}",
+            issue.code, issue.message
+        );
+    }
+    // The shadow rule set only reports where it disagrees, it changes neither tags nor messages
+    let divergence = config.shadow.as_ref().and_then(|shadow| {
+        DivergenceRecord::between(
+            &context.request_id,
+            &object,
+            Verdict::new(&config.rules, &report),
+            Verdict::new(&shadow.rules, &shadow.rules.validate(event_s3_attributes)),
+        )
+    });
+    if let Some(divergence) = &divergence {
+        warn!(
+            divergence = %serde_json::to_string(divergence)?,
+            "Shadow rule set {} disagrees with {} on {}",
+            divergence.shadow.rule_set, divergence.primary.rule_set, object.key
+        );
+    }
+
+    // If everything is okay, send a message to the success queues with the file identification
+
+    // If one or more things are wrong, compose a general message to send to the failure queues
+    // Add a quarantine tag to the file is something is wrong
+
+    let message_body = if report.is_valid() {
+        // File is valid, continue with processing
+        info!("{}", &validation_message);
+
+        single_tag(event_s3_attributes, store, &config.tags.validated).await?;
+
+        let mut applied_tags = add_tag(event_s3_attributes, store, &config.tags.valid).await?;
+        // Warnings let the file through, the tag makes them visible from outside the bucket
+        if !report.warnings().is_empty() {
+            applied_tags = add_tag(event_s3_attributes, store, &config.tags.warnings).await?;
+        }
+        // Copy the object to the curated location once it is tagged, the tags are copied along
+        let promoted_location = match &config.promotion {
+            Some(promotion) => Some(promote_object(store, promotion, &object).await?),
+            None => None,
+        };
+
+        ValidationMessageBody {
+            schema_version: SCHEMA_VERSION.to_string(),
+            workflow: config.workflow(&object.key, &report),
+            exc_id: context.request_id.to_owned(),
+            categories: config.categories(&object.key, &report),
+            message: validation_message.clone(),
+            object: object.clone(),
+            tags: MessageTag::from_tagging(&applied_tags),
+            continue_url: None,
+            abort_url: None,
+            quarantine_location: None,
+            promoted_location,
+            warnings: report.warnings(),
+        }
+    } else {
+        info!("File is invalid: {}", &validation_message);
+        single_tag(event_s3_attributes, store, &config.tags.validated).await?;
+
+        let applied_tags = add_tag(event_s3_attributes, store, &config.tags.quarantine).await?;
+        // Move the object to the quarantine location when configured and sign the links
+        let quarantined = quarantine_links(store, config, &object).await?;
+
+        ValidationMessageBody {
+            schema_version: SCHEMA_VERSION.to_string(),
+            workflow: config.workflow(&object.key, &report),
+            exc_id: context.request_id.to_owned(),
+            categories: config.categories(&object.key, &report),
+            message: validation_message.clone(),
+            object: object.clone(),
+            tags: MessageTag::from_tagging(&applied_tags),
+            continue_url: quarantined.continue_url,
+            abort_url: quarantined.abort_url,
+            quarantine_location: quarantined.location,
+            promoted_location: None,
+            warnings: Vec::new(),
+        }
+    };
+
+    if let Some(buffer) = buffer {
+        let outbound_message = OutboundMessage::new(
+            config,
+            encode_message(config, report.outcome(), message_body)?,
+            &object,
+            &report,
+            context.xray_trace_id.to_owned(),
+        );
+        // Detailed reports can exceed the SQS limit, they are then sent as a claim check
+        let outbound_message = offload_if_oversized(
+            store,
+            config.claim_check.as_ref(),
+            config.message_format,
+            outbound_message,
+        )
+        .await?;
+        // The routing table picks the destinations of the result, by default the success or failure queue
+        for destination in config.destinations(&object.key, &report) {
+            buffer.push(destination, record_index, outbound_message.clone());
+        }
+        let divergence_queue = config
+            .shadow
+            .as_ref()
+            .and_then(|shadow| shadow.queue_url.as_deref());
+        if let (Some(divergence), Some(queue_url)) = (divergence, divergence_queue) {
+            // The result of the version already uses its deduplication id, a FIFO queue
+            // shared with it would drop the divergence
+            let divergence_message = OutboundMessage {
+                deduplication_id: deduplication_id(&object, "divergence"),
+                ..OutboundMessage::new(
+                    config,
+                    serde_json::to_string(&divergence)?,
+                    &object,
+                    &report,
+                    context.xray_trace_id.to_owned(),
+                )
+            };
+            let divergence_message =
+                // Divergence records are never wrapped in an envelope
+                offload_if_oversized(
+                    store,
+                    config.claim_check.as_ref(),
+                    MessageFormat::Plain,
+                    divergence_message,
+                )
+                    .await?;
+            buffer.push(
+                Destination::sqs(queue_url),
+                record_index,
+                divergence_message,
+            );
+        }
+    }
+
+    Ok(RecordResult {
+        key: Some(object.key),
+        version_id: object.version_id,
+        outcome: Some(report.outcome()),
+        message: validation_message,
+        notifications: Vec::new(),
+    })
+}
+
+// The Success/Failure SQS queue URLs and tag names come from the Config loaded at cold start
+pub async fn function_handler(
+    event: LambdaEvent<S3Event>,
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+) -> Result<Response, Error> {
+    if event.payload.records.is_empty() {
+        return Err(Error::from("No records found in event"));
+    }
+
+    let records = process_records(
+        &event.payload.records,
+        &event.context,
+        store,
+        Some(notifier),
+        config,
+    )
+    .await?;
+
+    Ok(Response {
+        req_id: event.context.request_id,
+        message: records
+            .iter()
+            .map(|record| record.message.as_str())
+            .collect::<Vec<&str>>()
+            .join("; "),
+        records,
+    })
+}
+
+// Validate and tag the objects of the records. The notifications are only sent with a
+// notifier, the backfill can re-tag objects without notifying the consumers again.
+pub async fn process_records(
+    event_records: &[S3EventRecord],
+    context: &Context,
+    store: &dyn ObjectStore,
+    notifier: Option<&Notifier>,
+    config: &Config,
+) -> Result<Vec<RecordResult>, Error> {
+    // A record that can't be processed doesn't stop the others, it is reported and the
+    // invocation fails once every notification has been sent.
+    let mut buffer = MessageBuffer::new();
+    let mut records = Vec::new();
+    let mut failed_records = Vec::new();
+    for (record_index, record) in event_records.iter().enumerate() {
+        let bucket = record.s3.bucket.name.as_deref().unwrap_or_default();
+        let key = record.s3.object.key.as_deref().unwrap_or_default();
+        if config.is_internal_object(bucket, key) {
+            info!("Skipping s3://{}/{} written by the workflow", bucket, key);
+            records.push(RecordResult {
+                key: record.s3.object.key.to_owned(),
+                version_id: record.s3.object.version_id.to_owned(),
+                outcome: None,
+                message: "Skipped object written by the workflow".to_string(),
+                notifications: Vec::new(),
+            });
+            continue;
+        }
+        match validate_record(
+            record,
+            record_index,
+            context,
+            store,
+            config,
+            notifier.map(|_| &mut buffer),
+        )
+        .await
+        {
+            Ok(result) => records.push(result),
+            Err(e) => {
+                error!("Could not process record {}: {}", record_index, e);
+                failed_records.push(format!("record {}: {}", record_index, e));
+                records.push(RecordResult {
+                    key: record.s3.object.key.to_owned(),
+                    version_id: record.s3.object.version_id.to_owned(),
+                    outcome: None,
+                    message: e.to_string(),
+                    notifications: Vec::new(),
+                });
+            }
+        }
+    }
+
+    // Send the notifications of every record grouped per destination and report each entry back
+    let entries = match notifier {
+        Some(notifier) => buffer.flush(notifier, config).await,
+        None => Vec::new(),
+    };
+    for entry in entries {
+        if let Err(e) = &entry.result {
+            error!(
+                "Could not notify {} for record {}: {}",
+                entry.destination, entry.record_index, e
+            );
+            failed_records.push(format!(
+                "record {}: could not notify {}: {}",
+                entry.record_index, entry.destination, e
+            ));
+        }
+        let record_index = entry.record_index;
+        records[record_index]
+            .notifications
+            .push(NotificationResult::from(entry));
+    }
+
+    if !failed_records.is_empty() {
+        return Err(Error::from(failed_records.join(", ")));
+    }
+    Ok(records)
+}
+
+// An ObjectCreated record for an object version that is already in the bucket, to run it
+// through the same pipeline as an S3 notification
+pub fn synthetic_record(object: &ObjectIdentification) -> S3EventRecord {
+    let mut s3 = S3Entity::default();
+    s3.bucket.name = Some(object.bucket.clone());
+    // S3 URL encodes the key in the event, decoded_key reverses it
+    s3.object.key = Some(encode_key(&object.key));
+    s3.object.size = object.size;
+    s3.object.version_id = object.version_id.clone();
+    s3.object.e_tag = object.etag.clone();
+    S3EventRecord {
+        event_version: Some("2.1".to_string()),
+        event_source: Some("aws:s3".to_string()),
+        aws_region: None,
+        event_time: object.event_time,
+        event_name: Some("ObjectCreated:Copy".to_string()),
+        principal_id: S3UserIdentity {
+            principal_id: object.principal_id.clone(),
+        },
+        request_parameters: S3RequestParameters::default(),
+        response_elements: HashMap::new(),
+        s3,
+    }
+}
+
+#[cfg(test)]
+mod tests_synthetic_record {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_synthetic_record_round_trip() {
+        let object = ObjectIdentification {
+            bucket: "landing".to_string(),
+            key: "finance/1234 5678+90%.txt".to_string(),
+            version_id: Some("v1".to_string()),
+            etag: Some("abc".to_string()),
+            size: Some(42),
+            event_time: Utc::now(),
+            principal_id: None,
+        };
+        let record = synthetic_record(&object);
+        assert_eq!(ObjectIdentification::from_record(&record).unwrap(), object);
+    }
+}
+
+#[cfg(test)]
+mod tests_function_handler {
+    use super::*;
+    use crate::memory::{MemoryMessageQueue, MemoryObjectStore, StoreOperation};
+    use crate::quarantine::QuarantineConfig;
+    use crate::testing::{assert_object_tags, S3EventBuilder, S3RecordBuilder};
+    use std::sync::Arc;
+
+    const SUCCESS_QUEUE: &str = "https://sqs.eu-west-1.amazonaws.com/123456789012/success";
+    const FAILURE_QUEUE: &str = "https://sqs.eu-west-1.amazonaws.com/123456789012/failure";
+
+    fn config() -> Config {
+        Config {
+            success_queue_url: SUCCESS_QUEUE.to_string(),
+            failure_queue_url: FAILURE_QUEUE.to_string(),
+            ..Config::default()
+        }
+    }
+
+    fn notifier(queue: &Arc<MemoryMessageQueue>) -> Notifier {
+        Notifier::new(&aws_config::SdkConfig::builder().build()).with_message_queue(queue.clone())
+    }
+
+    // Upload an object to the store and build the event S3 sends for it
+    fn upload(store: &MemoryObjectStore, key: &str, body: &[u8]) -> LambdaEvent<S3Event> {
+        let version_id = store.insert("landing", key, body);
+        S3EventBuilder::new()
+            .record(
+                S3RecordBuilder::new("landing", key)
+                    .size(body.len() as i64)
+                    .version_id(&version_id),
+            )
+            .lambda_event("request-1")
+    }
+
+    #[tokio::test]
+    async fn test_valid_file_is_tagged_and_notified() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let event = upload(&store, "finance/1234-5678-9012-3456.txt", b"data");
+
+        let response = function_handler(event, &store, &notifier(&queue), &config())
+            .await
+            .unwrap();
+        assert_eq!(response.message, "File is valid");
+        assert_eq!(response.records[0].outcome, Some(Outcome::Success));
+        assert_object_tags(
+            &store,
+            "landing",
+            "finance/1234-5678-9012-3456.txt",
+            &[("validated", "true"), ("valid", "true")],
+        );
+
+        let messages = queue.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].queue_url, SUCCESS_QUEUE);
+        assert_eq!(messages[0].attributes["outcome"], "success");
+        assert_eq!(
+            response.records[0].notifications[0].message_id.as_deref(),
+            Some(messages[0].message_id.as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_warnings_let_the_file_through() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let event = upload(&store, "1234-5678-9012-ABCD.txt", b"data");
+        let mut config = config();
+        config
+            .rules
+            .severities
+            .insert("non_numeric_name".to_string(), Severity::Warning);
+
+        let response = function_handler(event, &store, &notifier(&queue), &config)
+            .await
+            .unwrap();
+        assert_eq!(response.records[0].outcome, Some(Outcome::Success));
+        assert_object_tags(
+            &store,
+            "landing",
+            "1234-5678-9012-ABCD.txt",
+            &[
+                ("validated", "true"),
+                ("valid", "true"),
+                ("validation:warnings", "true"),
+            ],
+        );
+        let body: ValidationMessageBody =
+            serde_json::from_str(&queue.messages_to(SUCCESS_QUEUE)[0].body).unwrap();
+        assert_eq!(
+            body.message,
+            "File is valid with warnings: Invalid file name format, it should be a numeric code"
+        );
+        assert_eq!(body.warnings.len(), 1);
+        assert_eq!(body.warnings[0].code, "non_numeric_name");
+    }
+
+    #[tokio::test]
+    async fn test_objects_use_the_profile_of_their_tenant() {
+        const FINANCE_QUEUE: &str = "https://sqs.eu-west-1.amazonaws.com/123456789012/finance";
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let mut config = config();
+        config.tenants = Some(
+            serde_json::from_value(serde_json::json!({
+                "default_profile": "platform",
+                "profiles": {
+                    "platform": {},
+                    "finance": {
+                        "rules": { "name": "finance", "extensions": ["csv"], "name_parts": null },
+                        "tag_namespace": "finance:",
+                        "success_queue_url": FINANCE_QUEUE,
+                        "categories": ["FIN-OPS"]
+                    }
+                },
+                "mappings": [{ "prefix": "finance/", "profile": "finance" }]
+            }))
+            .unwrap(),
+        );
+
+        let event = upload(&store, "finance/report.csv", b"data");
+        let response = function_handler(event, &store, &notifier(&queue), &config)
+            .await
+            .unwrap();
+        assert_eq!(response.records[0].outcome, Some(Outcome::Success));
+        assert_object_tags(
+            &store,
+            "landing",
+            "finance/report.csv",
+            &[("finance:validated", "true"), ("finance:valid", "true")],
+        );
+        let body: ValidationMessageBody =
+            serde_json::from_str(&queue.messages_to(FINANCE_QUEUE)[0].body).unwrap();
+        assert_eq!(body.categories, vec!["FIN-OPS"]);
+
+        // Other objects use the default profile
+        let event = upload(&store, "hr/report.csv", b"data");
+        let response = function_handler(event, &store, &notifier(&queue), &config)
+            .await
+            .unwrap();
+        assert_eq!(response.records[0].outcome, Some(Outcome::Failure));
+        assert_object_tags(
+            &store,
+            "landing",
+            "hr/report.csv",
+            &[("validated", "true"), ("quarentine", "true")],
+        );
+        assert_eq!(queue.messages_to(FAILURE_QUEUE).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shadow_divergence_is_reported_without_changing_the_verdict() {
+        const DIVERGENCE_QUEUE: &str =
+            "https://sqs.eu-west-1.amazonaws.com/123456789012/divergence.fifo";
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let mut config = config();
+        config.shadow = Some(
+            serde_json::from_value(serde_json::json!({
+                "rules": { "name": "v2", "extensions": ["txt", "csv"], "name_parts": null },
+                "queue_url": DIVERGENCE_QUEUE
+            }))
+            .unwrap(),
+        );
+
+        // Both rule sets accept a Prod ID, no divergence is sent
+        let event = upload(&store, "1234-5678-9012-3456.txt", b"data");
+        function_handler(event, &store, &notifier(&queue), &config)
+            .await
+            .unwrap();
+        assert!(queue
+            .take_messages()
+            .iter()
+            .all(|m| m.queue_url == SUCCESS_QUEUE));
+
+        // The shadow rule set accepts a report the primary one rejects
+        let event = upload(&store, "report.csv", b"data");
+        let response = function_handler(event, &store, &notifier(&queue), &config)
+            .await
+            .unwrap();
+        assert_eq!(response.records[0].outcome, Some(Outcome::Failure));
+        assert_object_tags(
+            &store,
+            "landing",
+            "report.csv",
+            &[("validated", "true"), ("quarentine", "true")],
+        );
+        assert_eq!(queue.messages_to(FAILURE_QUEUE).len(), 1);
+        let divergences = queue.messages_to(DIVERGENCE_QUEUE);
+        assert_eq!(divergences.len(), 1);
+        let record: serde_json::Value = serde_json::from_str(&divergences[0].body).unwrap();
+        assert_eq!(record["exc_id"], "request-1");
+        assert_eq!(record["object"]["key"], "report.csv");
+        assert_eq!(record["primary"]["rule_set"], "default");
+        assert_eq!(record["primary"]["outcome"], "failure");
+        assert_eq!(
+            record["primary"]["report"]["issues"][0]["code"],
+            "invalid_extension"
+        );
+        assert_eq!(record["shadow"]["rule_set"], "v2");
+        assert_eq!(record["shadow"]["outcome"], "success");
+        assert_eq!(record["shadow"]["report"]["issues"], serde_json::json!([]));
+        let object: ObjectIdentification =
+            serde_json::from_value(record["object"].clone()).unwrap();
+        assert_eq!(
+            divergences[0].deduplication_id,
+            Some(deduplication_id(&object, "divergence"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_file_is_quarantined() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let event = upload(&store, "report.csv", b"");
+
+        let response = function_handler(event, &store, &notifier(&queue), &config())
+            .await
+            .unwrap();
+        assert_eq!(response.records[0].outcome, Some(Outcome::Failure));
+        assert_object_tags(
+            &store,
+            "landing",
+            "report.csv",
+            &[("validated", "true"), ("quarentine", "true")],
+        );
+        assert_eq!(queue.messages_to(FAILURE_QUEUE).len(), 1);
+        assert!(queue.messages_to(SUCCESS_QUEUE).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_file_is_moved_to_quarantine() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let event = upload(&store, "report.csv", b"");
+        let config = Config {
+            quarantine: Some(serde_json::from_str::<QuarantineConfig>("{}").unwrap()),
+            ..config()
+        };
+
+        function_handler(event, &store, &notifier(&queue), &config)
+            .await
+            .unwrap();
+        // The original is hidden behind a delete marker, the copy keeps the tags
+        assert!(store.object("landing", "report.csv", None).is_none());
+        assert_object_tags(
+            &store,
+            "landing",
+            "quarantine/report.csv",
+            &[("validated", "true"), ("quarentine", "true")],
+        );
+        let body: serde_json::Value =
+            serde_json::from_str(&queue.messages_to(FAILURE_QUEUE)[0].body).unwrap();
+        assert_eq!(body["quarantine_location"]["key"], "quarantine/report.csv");
+    }
+
+    #[tokio::test]
+    async fn test_tagging_failure_fails_the_invocation() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let event = upload(&store, "1234-5678-9012-3456.txt", b"data");
+        store.fail(StoreOperation::PutObjectTagging, 1, "AccessDenied");
+
+        let error = function_handler(event, &store, &notifier(&queue), &config())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with(
+            "record 0: Original Error: AccessDenied Caused: Could not add tag validating"
+        ));
+        assert!(queue.messages().is_empty());
+        assert_object_tags(&store, "landing", "1234-5678-9012-3456.txt", &[]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_notification_is_retried() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let event = upload(&store, "1234-5678-9012-3456.txt", b"data");
+        queue.fail(SUCCESS_QUEUE, 1, "ServiceUnavailable", true);
+
+        let response = function_handler(event, &store, &notifier(&queue), &config())
+            .await
+            .unwrap();
+        assert_eq!(queue.messages_to(SUCCESS_QUEUE).len(), 1);
+        assert!(response.records[0].notifications[0].error.is_none());
+
+        let event = upload(&store, "2234-5678-9012-3456.txt", b"data");
+        queue.fail(SUCCESS_QUEUE, 1, "InvalidParameterValue", false);
+        let error = function_handler(event, &store, &notifier(&queue), &config())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("InvalidParameterValue"));
+    }
+}
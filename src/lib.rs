@@ -1,164 +1,318 @@
-mod generate_tags;
+pub mod backfill;
+pub mod batch;
+pub mod categories;
+pub mod claim_check;
+pub mod config;
+pub mod copy;
+pub mod dry_run;
+pub mod generate_tags;
+pub mod handler;
+pub mod links;
+pub mod local;
+pub mod memory;
+pub mod message;
+pub mod promotion;
+pub mod quarantine;
+pub mod routing;
+pub mod rules;
+pub mod shadow;
+pub mod sink;
+pub mod sqs;
+pub mod store;
+pub mod tenants;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod webhook;
+pub mod workflow;
 
 use crate::generate_tags::GenerateTags;
+use crate::rules::RuleSet;
+use crate::store::ObjectStore;
 use aws_lambda_events::s3::S3Entity;
 use aws_sdk_s3::model::Tagging;
-use aws_sdk_s3::output::PutObjectTaggingOutput;
-use aws_sdk_s3::Client as S3Client;
 use lambda_runtime::Error;
-use std::path::Path;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-fn check_file_extension(s3_entity: &S3Entity) -> Option<String> {
-    // Get the key of the object
-    let key = match &s3_entity.object.key {
-        Some(k) => k,
-        None => return Some("Missing object key".to_string()),
-    };
-
-    // Get the file extension
-    let file_extension = match Path::new(key).extension() {
-        Some(ext) => ext.to_str().unwrap(),
-        None => return Some("Missing file extension".to_string()),
-    };
+// Outcome of validating a single object
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
 
-    // Check if the file extension is .txt
-    if file_extension != "txt" {
-        return Some("Invalid file extension, should be .txt".to_string());
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+        }
     }
-    None
 }
 
-fn check_file_size(s3_entity: &S3Entity) -> Option<String> {
-    // Get the size of the object
-    let size = match s3_entity.object.size {
-        Some(s) => s,
-        None => return Some("Missing object size".to_string()),
-    };
+// Codes of the checks of a rule set
+pub const ERROR_CODES: &[&str] = &[
+    "missing_key",
+    "missing_extension",
+    "invalid_extension",
+    "missing_size",
+    "empty_file",
+    "file_too_small",
+    "file_too_large",
+    "invalid_name_format",
+    "non_numeric_name",
+];
+
+/// How much a failed check matters. Only errors make the file invalid, warnings let it through
+/// with a tag so new rules can be rolled out softly, info is only logged.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+}
 
-    // Check if the file size is not zero
-    if size <= 0 {
-        return Some("Invalid size, it should be greater than 0".to_string());
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
     }
-    None
 }
 
-fn check_file_name(s3_entity: &S3Entity) -> Option<String> {
-    let key = match &s3_entity.object.key {
-        Some(k) => k,
-        None => return Some("Missing object key".to_string()),
-    };
-    let file_name_without_ext = Path::new(key).file_stem().unwrap().to_str().unwrap();
-    let parts: Vec<&str> = file_name_without_ext.split("-").collect();
-    if parts.len() != 4 {
-        return Some("Invalid file name format, it should be formated as a Prod ID".to_string());
+impl std::str::FromStr for Severity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            other => Err(Error::from(format!(
+                "Unknown severity {}, expected info, warning or error",
+                other
+            ))),
+        }
     }
+}
 
-    for part in parts {
-        if !part.chars().all(|c| c.is_numeric()) {
-            return Some("Invalid file name format, it should be a numeric code".to_string());
+/// A failed check, identified by a stable code so it can be routed on and a human readable message.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub code: String,
+    pub message: String,
+    /// Issues of messages without a severity are errors.
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+impl ValidationIssue {
+    pub(crate) fn new(code: &str, message: &str) -> ValidationIssue {
+        ValidationIssue {
+            code: code.to_string(),
+            message: message.to_string(),
+            severity: Severity::Error,
         }
     }
-    None
 }
 
-pub fn is_valid_file(s3_entity: &S3Entity) -> (bool, String) {
-    let mut error_messages = Vec::new();
+// Result of running every check against an object
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    // Warnings and info don't make the file invalid
+    pub fn is_valid(&self) -> bool {
+        !self
+            .issues
+            .iter()
+            .any(|issue| issue.severity == Severity::Error)
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        if self.is_valid() {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        }
+    }
 
-    // Check the file extension
-    if let Some(error) = check_file_extension(s3_entity) {
-        error_messages.push(error);
+    // Highest severity of the issues, None when every check passed
+    pub fn severity(&self) -> Option<Severity> {
+        self.issues.iter().map(|issue| issue.severity).max()
     }
-    // Check the file size
-    if let Some(error) = check_file_size(s3_entity) {
-        error_messages.push(error);
+
+    // Codes of the checks that made the file invalid
+    pub fn error_codes(&self) -> Vec<&str> {
+        self.issues_with(Severity::Error)
+            .map(|issue| issue.code.as_str())
+            .collect()
     }
-    // Check the file name format
-    if let Some(error) = check_file_name(s3_entity) {
-        error_messages.push(error);
+
+    pub fn warnings(&self) -> Vec<ValidationIssue> {
+        self.issues_with(Severity::Warning).cloned().collect()
+    }
+
+    pub fn issues_with(&self, severity: Severity) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(move |issue| issue.severity == severity)
+    }
+
+    // Summary of the validation, the joined error messages when the file is invalid
+    // and the joined warnings when it passed with warnings
+    pub fn message(&self) -> String {
+        if !self.is_valid() {
+            return self.joined_messages(Severity::Error);
+        }
+        let warnings = self.joined_messages(Severity::Warning);
+        if warnings.is_empty() {
+            "File is valid".to_string()
+        } else {
+            format!("File is valid with warnings: {}", warnings)
+        }
     }
 
-    // If there are no error messages, the file is valid
-    if error_messages.is_empty() {
-        return (true, "File is valid".to_string());
+    fn joined_messages(&self, severity: Severity) -> String {
+        self.issues_with(severity)
+            .map(|issue| issue.message.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
     }
-    // Otherwise, join the error messages and return them
-    (false, error_messages.join(", "))
+
+    // Give the issues the severity configured for their code, errors by default
+    pub fn with_severities(mut self, severities: &BTreeMap<String, Severity>) -> ValidationReport {
+        for issue in &mut self.issues {
+            issue.severity = severities.get(&issue.code).copied().unwrap_or_default();
+        }
+        self
+    }
+}
+
+// Run the checks of the default rule set against the object, every failed check is an error
+pub fn validate_file(s3_entity: &S3Entity) -> ValidationReport {
+    RuleSet::default().validate(s3_entity)
+}
+
+pub fn is_valid_file(s3_entity: &S3Entity) -> (bool, String) {
+    let report = validate_file(s3_entity);
+    (report.is_valid(), report.message())
+}
+
+// The key as it is stored in the bucket. S3 URL encodes the key in the event, with spaces as +.
+pub fn decoded_key(s3_entity: &S3Entity) -> Result<String, Error> {
+    Ok(decode_key(
+        s3_entity.object.key.as_ref().ok_or("Missing object key")?,
+    ))
+}
+
+pub fn decode_key(encoded_key: &str) -> String {
+    let bytes = encoded_key.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'+' => decoded.push(b' '),
+            b'%' => match encoded_key
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    index += 2;
+                }
+                // Not an escape, kept as is
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| encoded_key.to_string())
+}
+
+// The key as S3 writes it in an event, the inverse of decode_key
+pub fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
 }
 
 pub async fn single_tag(
     event_s3_attributes: &S3Entity,
-    s3_client: &S3Client,
+    store: &dyn ObjectStore,
     tag_name: &str,
-) -> Result<PutObjectTaggingOutput, Error> {
+) -> Result<(), Error> {
     let bucket_name = event_s3_attributes
         .bucket
         .name
         .as_ref()
         .ok_or("Missing bucket name")?;
-    let object_key = &event_s3_attributes
-        .object
-        .key
-        .as_ref()
-        .ok_or("Missing object key")?
-        //handle the possibility of a file with an uwanted space, s3 adds a + to the event.
-        .replace("+", " ");
+    //handle the possibility of a file with an uwanted space, s3 adds a + to the event.
+    let object_key = &decoded_key(event_s3_attributes)?;
     let object_version_id = event_s3_attributes
         .object
         .version_id
         .as_ref()
         .ok_or("Object has no version ID defined, is versioning enabled in the bucket?")?;
 
-    let output = s3_client
-        .put_object_tagging()
-        .bucket(bucket_name)
-        .key(object_key)
-        .version_id(object_version_id
+    store
+        .put_object_tagging(
+            bucket_name,
+            object_key,
+            Some(object_version_id),
+            Tagging::tag_as_true(tag_name),
         )
-        .tagging(Tagging::tag_as_true(tag_name))
-        .send()
         .await
-        .map_err(|e| {
-            let original_error = e.into_service_error().to_string();
+        .map_err(|original_error| {
             Error::from(format!(
                 "Original Error: {} Caused: Could not add tag {} to Object s3://{}/{} versionId: {}",
                 original_error, tag_name, bucket_name, object_key, object_version_id
             ))
-        })?;
-    Ok(output)
+        })
 }
 
+// Add a tag to the object's current tags, returning the full tag set that was applied
 pub async fn add_tag(
     event_s3_attributes: &S3Entity,
-    s3_client: &S3Client,
+    store: &dyn ObjectStore,
     tag_name: &str,
-) -> Result<PutObjectTaggingOutput, Error> {
+) -> Result<Tagging, Error> {
     let bucket_name = event_s3_attributes
         .bucket
         .name
         .as_ref()
         .ok_or("Missing bucket name")?;
-    let object_key = &event_s3_attributes
-        .object
-        .key
-        .as_ref()
-        .ok_or("Missing object key")?
-        //handle the possibility of a file with an uwanted space, s3 adds a + to the event.
-        .replace("+", " ");
+    //handle the possibility of a file with an uwanted space, s3 adds a + to the event.
+    let object_key = &decoded_key(event_s3_attributes)?;
     let object_version_id = event_s3_attributes
         .object
         .version_id
         .as_ref()
         .ok_or("Object has no version ID defined, is versioning enabled in the bucket?")?;
 
-    let input: Tagging = s3_client
-        .get_object_tagging()
-        .bucket(bucket_name)
-        .key(object_key)
-        .version_id(object_version_id)
-        .send()
+    let input: Tagging = store
+        .get_object_tagging(bucket_name, object_key, Some(object_version_id))
         .await
-        .map_err(|e| {
-            let original_error = e.into_service_error().to_string();
+        .map_err(|original_error| {
             Error::from(format!(
                 "Original Error: {}; Could not get tags from to Object s3://{}/{} versionId: {}",
                 original_error, bucket_name, object_key, object_version_id
@@ -166,20 +320,100 @@ pub async fn add_tag(
         })?
         .add_true_tag(tag_name);
 
-    let output = s3_client
-        .put_object_tagging()
-        .bucket(bucket_name)
-        .key(object_key)
-        .version_id(object_version_id)
-        .tagging(input)
-        .send()
+    store
+        .put_object_tagging(
+            bucket_name,
+            object_key,
+            Some(object_version_id),
+            input.clone(),
+        )
         .await
-        .map_err(|e| {
-            let original_error = e.into_service_error().to_string();
+        .map_err(|original_error| {
             Error::from(format!(
                 "Original Error: {}; Could not add tag {} to Object s3://{}/{} versionId: {}",
                 original_error, tag_name, bucket_name, object_key, object_version_id
             ))
         })?;
-    Ok(output)
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests_key_encoding {
+    use super::*;
+
+    #[test]
+    fn test_decode_key_of_an_event() {
+        assert_eq!(decode_key("finance/report+1.txt"), "finance/report 1.txt");
+        assert_eq!(decode_key("a%2Bb%C3%A4.txt"), "a+bä.txt");
+        assert_eq!(decode_key("100%.txt"), "100%.txt");
+    }
+
+    #[test]
+    fn test_encode_key_round_trip() {
+        for key in [
+            "finance/1234 5678.txt",
+            "a+b.txt",
+            "r\u{e9}sum\u{e9} (1)%.csv",
+            "plain/key.txt",
+        ] {
+            assert_eq!(decode_key(&encode_key(key)), key);
+        }
+        assert_eq!(encode_key("a+b c.txt"), "a%2Bb+c.txt");
+    }
+}
+
+#[cfg(test)]
+mod tests_severity {
+    use super::*;
+
+    fn report(severities: &[(&str, Severity)]) -> ValidationReport {
+        let mut entity = S3Entity::default();
+        entity.object.key = Some("report-1.csv".to_string());
+        entity.object.size = Some(0);
+        let severities = severities
+            .iter()
+            .map(|(code, severity)| (code.to_string(), *severity))
+            .collect();
+        validate_file(&entity).with_severities(&severities)
+    }
+
+    #[test]
+    fn test_errors_make_the_file_invalid() {
+        let report = report(&[("empty_file", Severity::Warning)]);
+        assert!(!report.is_valid());
+        assert_eq!(report.severity(), Some(Severity::Error));
+        assert_eq!(
+            report.error_codes(),
+            vec!["invalid_extension", "invalid_name_format"]
+        );
+        assert_eq!(
+            report.message(),
+            "Invalid file extension, should be .txt, Invalid file name format, it should be formated as a Prod ID"
+        );
+    }
+
+    #[test]
+    fn test_warnings_and_info_pass() {
+        let report = report(&[
+            ("invalid_extension", Severity::Info),
+            ("empty_file", Severity::Warning),
+            ("invalid_name_format", Severity::Warning),
+        ]);
+        assert!(report.is_valid());
+        assert_eq!(report.outcome(), Outcome::Success);
+        assert_eq!(report.severity(), Some(Severity::Warning));
+        assert!(report.error_codes().is_empty());
+        assert_eq!(report.warnings().len(), 2);
+        assert_eq!(
+            report.message(),
+            "File is valid with warnings: Invalid size, it should be greater than 0, Invalid file name format, it should be formated as a Prod ID"
+        );
+    }
+
+    #[test]
+    fn test_issue_without_severity_is_an_error() {
+        let issue: ValidationIssue =
+            serde_json::from_str(r#"{ "code": "empty_file", "message": "Empty" }"#).unwrap();
+        assert_eq!(issue.severity, Severity::Error);
+    }
 }
@@ -0,0 +1,332 @@
+use crate::message::ObjectIdentification;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lambda_runtime::Error;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+// Links are valid for a week unless configured otherwise
+const DEFAULT_EXPIRY_SECONDS: i64 = 7 * 24 * 3600;
+
+// What the receiver of a failure message can decide for the quarantined object
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkAction {
+    // Release the object from quarantine and let it continue through the workflow
+    Continue,
+    // Mark the object as rejected
+    Abort,
+}
+
+impl LinkAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkAction::Continue => "continue",
+            LinkAction::Abort => "abort",
+        }
+    }
+}
+
+impl std::str::FromStr for LinkAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "continue" => Ok(LinkAction::Continue),
+            "abort" => Ok(LinkAction::Abort),
+            other => Err(Error::from(format!(
+                "Unknown action {}, expected continue or abort",
+                other
+            ))),
+        }
+    }
+}
+
+// Where the continue/abort links point to and how they are signed. Links are only
+// added to failure messages when this is configured.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LinksConfig {
+    // URL of the workflow HTTP handler, the action is appended as the last path segment
+    pub base_url: String,
+    // Environment variable holding the signing secret, shared with the handler
+    #[serde(default = "default_secret_env")]
+    pub secret_env: String,
+    #[serde(default = "default_expiry_seconds")]
+    pub expiry_seconds: i64,
+}
+
+fn default_secret_env() -> String {
+    "LINKS_SECRET".to_string()
+}
+
+fn default_expiry_seconds() -> i64 {
+    DEFAULT_EXPIRY_SECONDS
+}
+
+impl LinksConfig {
+    pub fn new(base_url: &str) -> LinksConfig {
+        LinksConfig {
+            base_url: base_url.to_string(),
+            secret_env: default_secret_env(),
+            expiry_seconds: default_expiry_seconds(),
+        }
+    }
+
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        if reqwest::Url::parse(&self.base_url).is_err() {
+            error_messages.push(format!("Invalid links base URL: {}", self.base_url));
+        }
+        if self.secret().is_err() {
+            error_messages.push(format!("Missing links secret in ${}", self.secret_env));
+        }
+        if self.expiry_seconds <= 0 {
+            error_messages.push("Links expiry must be greater than 0 seconds".to_string());
+        }
+        error_messages
+    }
+
+    pub fn secret(&self) -> Result<String, Error> {
+        match std::env::var(&self.secret_env) {
+            Ok(secret) if !secret.is_empty() => Ok(secret),
+            _ => Err(Error::from(format!(
+                "Environment variable {} is not set",
+                self.secret_env
+            ))),
+        }
+    }
+
+    // The continue and abort links of a quarantined object version
+    pub fn urls(&self, object: &ObjectIdentification) -> Result<(String, String), Error> {
+        let secret = self.secret()?;
+        let expires = Utc::now().timestamp() + self.expiry_seconds;
+        let url = |action| SignedLink::new(&secret, object, action, expires).url(&self.base_url);
+        Ok((url(LinkAction::Continue)?, url(LinkAction::Abort)?))
+    }
+}
+
+// An action on an object version, authenticated by an HMAC-SHA256 over every field
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedLink {
+    pub bucket: String,
+    pub key: String,
+    pub version_id: Option<String>,
+    pub action: LinkAction,
+    // Unix time after which the link is refused
+    pub expires: i64,
+    pub signature: String,
+}
+
+impl SignedLink {
+    pub fn new(
+        secret: &str,
+        object: &ObjectIdentification,
+        action: LinkAction,
+        expires: i64,
+    ) -> SignedLink {
+        let mut link = SignedLink {
+            bucket: object.bucket.clone(),
+            key: object.key.clone(),
+            version_id: object.version_id.clone(),
+            action,
+            expires,
+            signature: String::new(),
+        };
+        link.signature = hex::encode(link.mac(secret).finalize().into_bytes());
+        link
+    }
+
+    // Fields are separated by new lines, the key goes last as it is the only one that can contain one
+    fn mac(&self, secret: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(
+            format!(
+                "{}\n{}\n{}\n{}\n{}",
+                self.bucket,
+                self.version_id.as_deref().unwrap_or("null"),
+                self.action.as_str(),
+                self.expires,
+                self.key
+            )
+            .as_bytes(),
+        );
+        mac
+    }
+
+    pub fn url(&self, base_url: &str) -> Result<String, Error> {
+        let mut params = vec![
+            ("bucket", self.bucket.clone()),
+            ("key", self.key.clone()),
+            ("expires", self.expires.to_string()),
+            ("signature", self.signature.clone()),
+        ];
+        if let Some(version_id) = &self.version_id {
+            params.push(("version_id", version_id.clone()));
+        }
+        let url = reqwest::Url::parse_with_params(
+            &format!(
+                "{}/{}",
+                base_url.trim_end_matches('/'),
+                self.action.as_str()
+            ),
+            params,
+        )
+        .map_err(|e| {
+            Error::from(format!(
+                "Original Error: {}; Could not build link from {}",
+                e, base_url
+            ))
+        })?;
+        Ok(url.to_string())
+    }
+
+    // Read a link back from the action in the path and the query string parameters
+    pub fn from_query<F>(action: &str, param: F) -> Result<SignedLink, Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let required = |name: &str| param(name).ok_or(format!("Missing parameter {}", name));
+        let expires = required("expires")?;
+        Ok(SignedLink {
+            bucket: required("bucket")?,
+            key: required("key")?,
+            version_id: param("version_id"),
+            action: action.parse()?,
+            expires: expires
+                .parse()
+                .map_err(|_| Error::from(format!("Invalid expiry: {}", expires)))?,
+            signature: required("signature")?,
+        })
+    }
+
+    pub fn verify(&self, secret: &str, now: i64) -> Result<(), Error> {
+        let signature =
+            hex::decode(&self.signature).map_err(|_| Error::from("Invalid link signature"))?;
+        // verify_slice compares in constant time
+        self.mac(secret)
+            .verify_slice(&signature)
+            .map_err(|_| Error::from("Invalid link signature"))?;
+        if now > self.expires {
+            return Err(Error::from("Link has expired"));
+        }
+        Ok(())
+    }
+}
+
+// Opening a link only shows this page, the action runs when its form is posted. Mail scanners
+// and link prefetchers open links but don't submit forms. The form posts to the URL of the page,
+// so the signed parameters are sent along.
+pub fn confirmation_page(link: &SignedLink) -> String {
+    let (question, button) = match link.action {
+        LinkAction::Continue => ("Release this file from quarantine?", "Release"),
+        LinkAction::Abort => ("Reject this quarantined file?", "Reject"),
+    };
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{question}</title></head>\n\
+         <body>\n<p>{question}</p>\n<p>s3://{bucket}/{key}</p>\n\
+         <form method=\"post\"><button type=\"submit\">{button}</button></form>\n</body>\n</html>\n",
+        question = question,
+        bucket = html_escape(&link.bucket),
+        key = html_escape(&link.key),
+        button = button,
+    )
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests_signed_links {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn object() -> ObjectIdentification {
+        ObjectIdentification {
+            bucket: "landing".to_string(),
+            key: "finance/1234 5678&.txt".to_string(),
+            version_id: Some("v1".to_string()),
+            etag: None,
+            size: Some(42),
+            event_time: Utc::now(),
+            principal_id: None,
+        }
+    }
+
+    fn parse(url: &str) -> SignedLink {
+        let url = reqwest::Url::parse(url).unwrap();
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let action = url
+            .path_segments()
+            .unwrap()
+            .next_back()
+            .unwrap()
+            .to_string();
+        SignedLink::from_query(&action, |name| params.get(name).cloned()).unwrap()
+    }
+
+    #[test]
+    fn test_link_round_trip() {
+        let link = SignedLink::new("s3cr3t", &object(), LinkAction::Abort, 2000);
+        let url = link.url("https://links.example.com/workflow/").unwrap();
+        assert!(url.starts_with("https://links.example.com/workflow/abort?bucket=landing&"));
+        let parsed = parse(&url);
+        assert_eq!(parsed, link);
+        assert!(parsed.verify("s3cr3t", 1000).is_ok());
+    }
+
+    #[test]
+    fn test_tampered_or_expired_link_is_refused() {
+        let link = SignedLink::new("s3cr3t", &object(), LinkAction::Abort, 2000);
+        assert!(link.verify("other", 1000).is_err());
+        assert_eq!(
+            link.verify("s3cr3t", 2001).unwrap_err().to_string(),
+            "Link has expired"
+        );
+        let continued = SignedLink {
+            action: LinkAction::Continue,
+            ..link.clone()
+        };
+        assert!(continued.verify("s3cr3t", 1000).is_err());
+        let other_version = SignedLink {
+            version_id: Some("v2".to_string()),
+            ..link.clone()
+        };
+        assert!(other_version.verify("s3cr3t", 1000).is_err());
+        let extended = SignedLink {
+            expires: 3000,
+            ..link
+        };
+        assert!(extended.verify("s3cr3t", 1000).is_err());
+    }
+
+    #[test]
+    fn test_confirmation_page_posts_the_form() {
+        let object = ObjectIdentification {
+            key: "finance/<b>1234&5678.txt".to_string(),
+            ..object()
+        };
+        let page = confirmation_page(&SignedLink::new(
+            "s3cr3t",
+            &object,
+            LinkAction::Continue,
+            2000,
+        ));
+        assert!(page.contains("<form method=\"post\">"));
+        assert!(page.contains("Release this file from quarantine?"));
+        assert!(page.contains("s3://landing/finance/&lt;b&gt;1234&amp;5678.txt"));
+    }
+
+    #[test]
+    fn test_missing_parameters() {
+        let error = SignedLink::from_query("continue", |_| None).unwrap_err();
+        assert_eq!(error.to_string(), "Missing parameter expires");
+        assert!(SignedLink::from_query("delete", |_| Some("1".to_string())).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use crate::rules::RuleSet;
+use crate::{Outcome, ValidationIssue};
+use aws_lambda_events::event::s3::{S3Entity, S3Event};
+use aws_lambda_events::event::sns::SnsEvent;
+use aws_lambda_events::event::sqs::SqsEvent;
+use lambda_runtime::Error;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+// Result of running the rules against a file on disk, before it is uploaded
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LocalReport {
+    pub path: String,
+    // Key the file would have in the bucket
+    pub key: String,
+    pub size: i64,
+    pub outcome: Outcome,
+    pub message: String,
+    pub issues: Vec<ValidationIssue>,
+}
+
+// The rules look at the key and size of the object, the key is the file name under the given prefix.
+// The rule set should be the one of the config of the Lambda.
+pub fn validate_local_file(
+    path: &Path,
+    prefix: &str,
+    rules: &RuleSet,
+) -> Result<LocalReport, Error> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| Error::from(format!("Could not read {}: {}", path.display(), e)))?;
+    if !metadata.is_file() {
+        return Err(Error::from(format!("{} is not a file", path.display())));
+    }
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::from(format!("Invalid file name: {}", path.display())))?;
+    let key = format!("{}{}", prefix, file_name);
+    let size = metadata.len() as i64;
+
+    let mut entity = S3Entity::default();
+    entity.object.key = Some(key.clone());
+    entity.object.size = Some(size);
+    let report = rules.validate(&entity);
+    Ok(LocalReport {
+        path: path.display().to_string(),
+        key,
+        size,
+        outcome: report.outcome(),
+        message: report.message(),
+        issues: report.issues,
+    })
+}
+
+// Read the S3 events of a fixture. Besides an S3 event, it can be an SQS or SNS event carrying
+// S3 notifications, an SNS notification as it is delivered to a queue, or an array of those.
+pub fn s3_events_from_json(contents: &str) -> Result<Vec<S3Event>, Error> {
+    let value: Value = serde_json::from_str(contents)
+        .map_err(|e| Error::from(format!("Original Error: {}; Could not parse event", e)))?;
+    s3_events_from_value(value)
+}
+
+fn s3_events_from_str(contents: &str) -> Result<Vec<S3Event>, Error> {
+    let value: Value = serde_json::from_str(contents).map_err(|e| {
+        Error::from(format!(
+            "Original Error: {}; Could not parse the S3 notification of the message",
+            e
+        ))
+    })?;
+    s3_events_from_value(value)
+}
+
+fn s3_events_from_value(value: Value) -> Result<Vec<S3Event>, Error> {
+    if let Value::Array(values) = value {
+        let mut events = Vec::new();
+        for value in values {
+            events.extend(s3_events_from_value(value)?);
+        }
+        return Ok(events);
+    }
+    // S3 sends a test event when the notification is set up, there is nothing to validate
+    if value.get("Event").and_then(Value::as_str) == Some("s3:TestEvent") {
+        return Ok(Vec::new());
+    }
+    if value.get("Type").and_then(Value::as_str) == Some("Notification") {
+        let message = value
+            .get("Message")
+            .and_then(Value::as_str)
+            .ok_or("Missing Message in the SNS notification")?;
+        return s3_events_from_str(message);
+    }
+
+    let source = value
+        .get("Records")
+        .and_then(Value::as_array)
+        .ok_or("Unsupported event, expected Records")?
+        .first()
+        .and_then(|record| {
+            record
+                .get("eventSource")
+                .or_else(|| record.get("EventSource"))
+        })
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    match source.as_str() {
+        "aws:s3" => Ok(vec![serde_json::from_value(value).map_err(|e| {
+            Error::from(format!("Original Error: {}; Could not parse S3 event", e))
+        })?]),
+        "aws:sqs" => {
+            let event: SqsEvent = serde_json::from_value(value).map_err(|e| {
+                Error::from(format!("Original Error: {}; Could not parse SQS event", e))
+            })?;
+            let mut events = Vec::new();
+            for message in event.records {
+                events.extend(s3_events_from_str(
+                    message.body.as_deref().unwrap_or_default(),
+                )?);
+            }
+            Ok(events)
+        }
+        "aws:sns" => {
+            let event: SnsEvent = serde_json::from_value(value).map_err(|e| {
+                Error::from(format!("Original Error: {}; Could not parse SNS event", e))
+            })?;
+            let mut events = Vec::new();
+            for record in event.records {
+                events.extend(s3_events_from_str(&record.sns.message)?);
+            }
+            Ok(events)
+        }
+        "" => Err(Error::from("Unsupported event, expected an event source")),
+        source => Err(Error::from(format!("Unsupported event source {}", source))),
+    }
+}
+
+#[cfg(test)]
+mod tests_local_file {
+    use super::*;
+    use crate::Severity;
+    use std::path::PathBuf;
+
+    fn write_file(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("local-validation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_valid_local_file() {
+        let path = write_file("1234-5678-9012-3456.txt", "data");
+        let report = validate_local_file(&path, "finance/", &RuleSet::default()).unwrap();
+        assert_eq!(report.key, "finance/1234-5678-9012-3456.txt");
+        assert_eq!(report.size, 4);
+        assert_eq!(report.outcome, Outcome::Success);
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_local_file() {
+        let path = write_file("report.csv", "");
+        let report = validate_local_file(&path, "", &RuleSet::default()).unwrap();
+        assert_eq!(report.outcome, Outcome::Failure);
+        assert_eq!(
+            report
+                .issues
+                .iter()
+                .map(|issue| issue.code.as_str())
+                .collect::<Vec<_>>(),
+            vec!["invalid_extension", "empty_file", "invalid_name_format"]
+        );
+    }
+
+    #[test]
+    fn test_local_file_with_warnings() {
+        let path = write_file("1234-5678-9012-ABCD.txt", "data");
+        let mut rules = RuleSet::default();
+        rules
+            .severities
+            .insert("non_numeric_name".to_string(), Severity::Warning);
+        let report = validate_local_file(&path, "", &rules).unwrap();
+        assert_eq!(report.outcome, Outcome::Success);
+        assert_eq!(
+            report.message,
+            "File is valid with warnings: Invalid file name format, it should be a numeric code"
+        );
+        assert_eq!(report.issues[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_missing_local_file() {
+        let error = validate_local_file(Path::new("/does/not/exist.txt"), "", &RuleSet::default())
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Could not read /does/not/exist.txt"));
+    }
+}
+
+#[cfg(test)]
+mod tests_event_fixtures {
+    use super::*;
+
+    const S3_EVENT: &str = r#"{
+        "Records": [{
+            "eventVersion": "2.1",
+            "eventSource": "aws:s3",
+            "awsRegion": "eu-west-1",
+            "eventTime": "2023-02-01T10:15:30.000Z",
+            "eventName": "ObjectCreated:Put",
+            "userIdentity": { "principalId": "AWS:AIDAEXAMPLE" },
+            "requestParameters": { "sourceIPAddress": "127.0.0.1" },
+            "responseElements": {},
+            "s3": {
+                "s3SchemaVersion": "1.0",
+                "configurationId": "validation",
+                "bucket": {
+                    "name": "landing",
+                    "ownerIdentity": { "principalId": "A3NL1KOZZKExample" },
+                    "arn": "arn:aws:s3:::landing"
+                },
+                "object": {
+                    "key": "finance/1234-5678-9012-3456.txt",
+                    "size": 42,
+                    "versionId": "v1",
+                    "eTag": "abc",
+                    "sequencer": "0A1B2C3D4E5F678901"
+                }
+            }
+        }]
+    }"#;
+
+    fn key(event: &S3Event) -> &str {
+        event.records[0].s3.object.key.as_deref().unwrap()
+    }
+
+    #[test]
+    fn test_s3_event() {
+        let events = s3_events_from_json(S3_EVENT).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(key(&events[0]), "finance/1234-5678-9012-3456.txt");
+    }
+
+    #[test]
+    fn test_s3_event_through_sns_and_sqs() {
+        let notification = serde_json::json!({
+            "Type": "Notification",
+            "MessageId": "1",
+            "TopicArn": "arn:aws:sns:eu-west-1:123456789012:uploads",
+            "Message": S3_EVENT,
+        });
+        let sqs_event = serde_json::json!({
+            "Records": [
+                { "messageId": "1", "eventSource": "aws:sqs", "body": notification.to_string() },
+                { "messageId": "2", "eventSource": "aws:sqs", "body": S3_EVENT },
+                { "messageId": "3", "eventSource": "aws:sqs", "body": r#"{ "Event": "s3:TestEvent" }"# }
+            ]
+        });
+        let events = s3_events_from_json(&sqs_event.to_string()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events
+            .iter()
+            .all(|event| key(event) == "finance/1234-5678-9012-3456.txt"));
+    }
+
+    #[test]
+    fn test_unsupported_event() {
+        let error = s3_events_from_json(r#"{ "Records": [{ "eventSource": "aws:dynamodb" }] }"#)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Unsupported event source aws:dynamodb");
+        assert!(s3_events_from_json(r#"[{ "Event": "s3:TestEvent" }]"#)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,683 @@
+use crate::message::{MessageTag, ObjectIdentification, ObjectLocation};
+use crate::sink::Delivery;
+use crate::sqs::{MessageQueue, OutboundMessage, Queue};
+use crate::store::{ObjectHead, ObjectPage, ObjectStore, LIST_PAGE_SIZE};
+use async_trait::async_trait;
+use aws_sdk_s3::model::Tagging;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+
+// In-memory ObjectStore and MessageQueue, to run the handler without AWS in tests and in
+// the local runner. Every call is recorded and failures can be injected per operation.
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreOperation {
+    GetObjectTagging,
+    PutObjectTagging,
+    HeadObject,
+    GetObject,
+    PutObject,
+    CopyObject,
+    DeleteObject,
+    ListObjects,
+}
+
+// A call made to the MemoryObjectStore, the key is the prefix of a listing
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StoreCall {
+    pub operation: StoreOperation,
+    pub bucket: String,
+    pub key: String,
+    pub version_id: Option<String>,
+    // Tags written by PutObjectTagging
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<MessageTag>>,
+    // Where CopyObject wrote the copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copy: Option<ObjectLocation>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredObject {
+    pub body: Vec<u8>,
+    pub content_type: Option<String>,
+    pub tagging: Tagging,
+    pub last_modified: DateTime<Utc>,
+}
+
+impl StoredObject {
+    fn new(body: &[u8]) -> StoredObject {
+        StoredObject {
+            body: body.to_vec(),
+            content_type: None,
+            tagging: Tagging::builder().build(),
+            last_modified: Utc::now(),
+        }
+    }
+}
+
+// The next calls of an operation fail with the error
+#[derive(Debug, Clone)]
+struct Failure {
+    remaining: usize,
+    error: String,
+    retryable: bool,
+}
+
+fn take_failure<K: std::hash::Hash + Eq>(
+    failures: &mut HashMap<K, Failure>,
+    key: &K,
+) -> Option<Failure> {
+    let failure = failures.get_mut(key)?;
+    failure.remaining -= 1;
+    let taken = failure.clone();
+    if failure.remaining == 0 {
+        failures.remove(key);
+    }
+    Some(taken)
+}
+
+// Version ids of a key with their content, oldest first. None is a delete marker.
+type Versions = Vec<(String, Option<StoredObject>)>;
+
+#[derive(Default)]
+struct StoreState {
+    objects: BTreeMap<(String, String), Versions>,
+    calls: Vec<StoreCall>,
+    failures: HashMap<StoreOperation, Failure>,
+    next_version: u64,
+}
+
+impl StoreState {
+    fn new_version_id(&mut self) -> String {
+        self.next_version += 1;
+        format!("v{}", self.next_version)
+    }
+
+    fn version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<(String, &StoredObject), Error> {
+        let versions = self
+            .objects
+            .get(&(bucket.to_string(), key.to_string()))
+            .ok_or("NoSuchKey: The specified key does not exist.")?;
+        let found = match version_id {
+            Some(version_id) => versions.iter().find(|(id, _)| id == version_id),
+            None => versions.last(),
+        };
+        match found {
+            Some((id, Some(object))) => Ok((id.clone(), object)),
+            Some((_, None)) if version_id.is_none() => {
+                Err(Error::from("NoSuchKey: The specified key does not exist."))
+            }
+            _ => Err(Error::from(
+                "NoSuchVersion: The specified version does not exist.",
+            )),
+        }
+    }
+
+    fn insert(&mut self, bucket: &str, key: &str, object: Option<StoredObject>) -> String {
+        let version_id = self.new_version_id();
+        self.objects
+            .entry((bucket.to_string(), key.to_string()))
+            .or_default()
+            .push((version_id.clone(), object));
+        version_id
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryObjectStore {
+    state: Mutex<StoreState>,
+}
+
+impl MemoryObjectStore {
+    pub fn new() -> MemoryObjectStore {
+        MemoryObjectStore::default()
+    }
+
+    // Add a new version of an object, returns its version id
+    pub fn insert(&self, bucket: &str, key: &str, body: &[u8]) -> String {
+        let mut state = self.state.lock().unwrap();
+        state.insert(bucket, key, Some(StoredObject::new(body)))
+    }
+
+    // Add a version with a known id, e.g. the version of an event fixture
+    pub fn insert_version(&self, bucket: &str, key: &str, version_id: &str, body: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .objects
+            .entry((bucket.to_string(), key.to_string()))
+            .or_default()
+            .push((version_id.to_string(), Some(StoredObject::new(body))));
+    }
+
+    pub fn object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Option<StoredObject> {
+        let state = self.state.lock().unwrap();
+        state
+            .version(bucket, key, version_id)
+            .ok()
+            .map(|(_, object)| object.clone())
+    }
+
+    // Tags of a version as name and value pairs, None when the version doesn't exist
+    pub fn tags(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Option<Vec<(String, String)>> {
+        self.object(bucket, key, version_id).map(|object| {
+            MessageTag::from_tagging(&object.tagging)
+                .into_iter()
+                .map(|tag| (tag.key, tag.value))
+                .collect()
+        })
+    }
+
+    pub fn calls(&self) -> Vec<StoreCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    // Return the calls recorded so far and start a new record
+    pub fn take_calls(&self) -> Vec<StoreCall> {
+        std::mem::take(&mut self.state.lock().unwrap().calls)
+    }
+
+    // The next `times` calls of the operation fail with the error, usize::MAX for every call
+    pub fn fail(&self, operation: StoreOperation, times: usize, error: &str) {
+        self.state.lock().unwrap().failures.insert(
+            operation,
+            Failure {
+                remaining: times,
+                error: error.to_string(),
+                retryable: false,
+            },
+        );
+    }
+
+    // Record the call, then fail it if a failure was injected
+    fn record(&self, state: &mut StoreState, call: StoreCall) -> Result<(), Error> {
+        let operation = call.operation;
+        state.calls.push(call);
+        match take_failure(&mut state.failures, &operation) {
+            Some(failure) => Err(Error::from(failure.error)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn store_call(
+    operation: StoreOperation,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+) -> StoreCall {
+    StoreCall {
+        operation,
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        version_id: version_id.map(str::to_string),
+        tags: None,
+        copy: None,
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MemoryObjectStore {
+    async fn get_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<Tagging, Error> {
+        let mut state = self.state.lock().unwrap();
+        self.record(
+            &mut state,
+            store_call(StoreOperation::GetObjectTagging, bucket, key, version_id),
+        )?;
+        let (_, object) = state.version(bucket, key, version_id)?;
+        Ok(object.tagging.clone())
+    }
+
+    async fn put_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        tagging: Tagging,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        self.record(
+            &mut state,
+            StoreCall {
+                tags: Some(MessageTag::from_tagging(&tagging)),
+                ..store_call(StoreOperation::PutObjectTagging, bucket, key, version_id)
+            },
+        )?;
+        let (version_id, _) = state.version(bucket, key, version_id)?;
+        if let Some((_, Some(object))) = state
+            .objects
+            .get_mut(&(bucket.to_string(), key.to_string()))
+            .and_then(|versions| versions.iter_mut().find(|(id, _)| *id == version_id))
+        {
+            object.tagging = tagging;
+        }
+        Ok(())
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<ObjectHead, Error> {
+        let mut state = self.state.lock().unwrap();
+        self.record(
+            &mut state,
+            store_call(StoreOperation::HeadObject, bucket, key, version_id),
+        )?;
+        let (version_id, object) = state.version(bucket, key, version_id)?;
+        Ok(ObjectHead {
+            version_id: Some(version_id),
+            e_tag: None,
+            content_length: object.body.len() as i64,
+        })
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<Vec<u8>, Error> {
+        let mut state = self.state.lock().unwrap();
+        self.record(
+            &mut state,
+            store_call(StoreOperation::GetObject, bucket, key, None),
+        )?;
+        let (_, object) = state.version(bucket, key, None)?;
+        Ok(object.body.clone())
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        self.record(
+            &mut state,
+            store_call(StoreOperation::PutObject, bucket, key, None),
+        )?;
+        state.insert(
+            bucket,
+            key,
+            Some(StoredObject {
+                body,
+                content_type: Some(content_type.to_string()),
+                tagging: Tagging::builder().build(),
+                last_modified: Utc::now(),
+            }),
+        );
+        Ok(())
+    }
+
+    async fn copy_object(
+        &self,
+        source: &ObjectIdentification,
+        bucket: &str,
+        key: &str,
+    ) -> Result<ObjectLocation, Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut location = ObjectLocation {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            version_id: None,
+        };
+        self.record(
+            &mut state,
+            StoreCall {
+                copy: Some(location.clone()),
+                ..store_call(
+                    StoreOperation::CopyObject,
+                    &source.bucket,
+                    &source.key,
+                    source.version_id.as_deref(),
+                )
+            },
+        )?;
+        // The metadata and tags are copied along
+        let (_, object) =
+            state.version(&source.bucket, &source.key, source.version_id.as_deref())?;
+        let copy = StoredObject {
+            last_modified: Utc::now(),
+            ..object.clone()
+        };
+        location.version_id = Some(state.insert(bucket, key, Some(copy)));
+        Ok(location)
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        self.record(
+            &mut state,
+            store_call(StoreOperation::DeleteObject, bucket, key, version_id),
+        )?;
+        match version_id {
+            Some(version_id) => {
+                if let Some(versions) = state
+                    .objects
+                    .get_mut(&(bucket.to_string(), key.to_string()))
+                {
+                    versions.retain(|(id, _)| id != version_id);
+                }
+            }
+            None => {
+                state.insert(bucket, key, None);
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        all_versions: bool,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+    ) -> Result<ObjectPage, Error> {
+        let mut state = self.state.lock().unwrap();
+        self.record(
+            &mut state,
+            store_call(StoreOperation::ListObjects, bucket, prefix, None),
+        )?;
+        // Like S3: keys in order, the versions of a key from the newest
+        let mut listed = Vec::new();
+        let mut noncurrent = BTreeSet::new();
+        for ((object_bucket, key), versions) in &state.objects {
+            if object_bucket != bucket || !key.starts_with(prefix) {
+                continue;
+            }
+            for (index, (version_id, object)) in versions.iter().rev().enumerate() {
+                // Every version but the newest one is hidden, by it or by a delete marker
+                if all_versions && index > 0 {
+                    noncurrent.insert((key.clone(), version_id.clone()));
+                }
+                if let Some(object) = object {
+                    listed.push(ObjectIdentification {
+                        bucket: bucket.to_string(),
+                        key: key.clone(),
+                        version_id: all_versions.then(|| version_id.clone()),
+                        etag: None,
+                        size: Some(object.body.len() as i64),
+                        event_time: object.last_modified,
+                        principal_id: None,
+                    });
+                }
+                // Without versions, a key is listed when its latest version is an object
+                if !all_versions {
+                    break;
+                }
+            }
+        }
+        let start = match key_marker {
+            Some(key_marker) => listed
+                .iter()
+                .position(|object| {
+                    object.key == key_marker
+                        && (!all_versions
+                            || version_id_marker.is_none()
+                            || object.version_id.as_deref() == version_id_marker)
+                })
+                .map(|position| position + 1)
+                .unwrap_or_else(|| {
+                    listed
+                        .iter()
+                        .position(|object| object.key.as_str() > key_marker)
+                        .unwrap_or(listed.len())
+                }),
+            None => 0,
+        };
+        let objects: Vec<ObjectIdentification> = listed
+            .iter()
+            .skip(start)
+            .take(LIST_PAGE_SIZE as usize)
+            .cloned()
+            .collect();
+        let next = match objects.last() {
+            Some(last) if start + objects.len() < listed.len() => {
+                Some((last.key.clone(), last.version_id.clone()))
+            }
+            _ => None,
+        };
+        // Only the versions of the page
+        let noncurrent = objects
+            .iter()
+            .filter_map(|object| {
+                let version = (object.key.clone(), object.version_id.clone()?);
+                noncurrent.contains(&version).then_some(version)
+            })
+            .collect();
+        Ok(ObjectPage {
+            objects,
+            noncurrent,
+            next,
+        })
+    }
+}
+
+// A message received by the MemoryMessageQueue
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SentMessage {
+    pub queue_url: String,
+    pub message_id: String,
+    pub body: String,
+    pub attributes: BTreeMap<String, String>,
+    // Only set for FIFO queues, like SQS
+    pub group_id: Option<String>,
+    pub deduplication_id: Option<String>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    messages: Vec<SentMessage>,
+    failures: HashMap<String, Failure>,
+    next_id: u64,
+}
+
+#[derive(Default)]
+pub struct MemoryMessageQueue {
+    state: Mutex<QueueState>,
+}
+
+impl MemoryMessageQueue {
+    pub fn new() -> MemoryMessageQueue {
+        MemoryMessageQueue::default()
+    }
+
+    pub fn messages(&self) -> Vec<SentMessage> {
+        self.state.lock().unwrap().messages.clone()
+    }
+
+    pub fn messages_to(&self, queue_url: &str) -> Vec<SentMessage> {
+        self.messages()
+            .into_iter()
+            .filter(|message| message.queue_url == queue_url)
+            .collect()
+    }
+
+    // Return the messages received so far and start a new record
+    pub fn take_messages(&self) -> Vec<SentMessage> {
+        std::mem::take(&mut self.state.lock().unwrap().messages)
+    }
+
+    // The next `times` messages sent to the queue fail with the error
+    pub fn fail(&self, queue_url: &str, times: usize, error: &str, retryable: bool) {
+        self.state.lock().unwrap().failures.insert(
+            queue_url.to_string(),
+            Failure {
+                remaining: times,
+                error: error.to_string(),
+                retryable,
+            },
+        );
+    }
+
+    fn receive(&self, queue: &Queue, message: &OutboundMessage) -> Delivery {
+        let mut state = self.state.lock().unwrap();
+        if let Some(failure) = take_failure(&mut state.failures, &queue.url) {
+            return Delivery::Failed {
+                error: failure.error,
+                retryable: failure.retryable,
+            };
+        }
+        state.next_id += 1;
+        let message_id = format!("message-{}", state.next_id);
+        state.messages.push(SentMessage {
+            queue_url: queue.url.clone(),
+            message_id: message_id.clone(),
+            body: message.body.clone(),
+            attributes: message.attributes.clone(),
+            group_id: queue.fifo.then(|| message.group_id.clone()),
+            deduplication_id: queue.fifo.then(|| message.deduplication_id.clone()),
+        });
+        Delivery::Sent(message_id)
+    }
+}
+
+#[async_trait]
+impl MessageQueue for MemoryMessageQueue {
+    async fn send_message(
+        &self,
+        queue: &Queue,
+        message: &OutboundMessage,
+    ) -> Result<String, Error> {
+        match self.receive(queue, message) {
+            Delivery::Sent(message_id) => Ok(message_id),
+            Delivery::Failed { error, .. } => Err(Error::from(error)),
+        }
+    }
+
+    async fn send_message_batch(
+        &self,
+        queue: &Queue,
+        messages: &[&OutboundMessage],
+    ) -> Vec<Delivery> {
+        messages
+            .iter()
+            .map(|message| self.receive(queue, message))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests_memory_store {
+    use super::*;
+
+    fn keys(page: &ObjectPage) -> Vec<(String, Option<String>)> {
+        page.objects
+            .iter()
+            .map(|object| (object.key.clone(), object.version_id.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_versions_and_delete_markers() {
+        let store = MemoryObjectStore::new();
+        let first = store.insert("landing", "a.txt", b"one");
+        let second = store.insert("landing", "a.txt", b"two");
+        assert_eq!(store.get_object("landing", "a.txt").await.unwrap(), b"two");
+
+        store.delete_object("landing", "a.txt", None).await.unwrap();
+        assert!(store.get_object("landing", "a.txt").await.is_err());
+        let head = store
+            .head_object("landing", "a.txt", Some(&first))
+            .await
+            .unwrap();
+        assert_eq!(head.content_length, 3);
+
+        store
+            .delete_object("landing", "a.txt", Some(&second))
+            .await
+            .unwrap();
+        let error = store
+            .head_object("landing", "a.txt", Some(&second))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with("NoSuchVersion"));
+        assert_eq!(store.calls().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_list_objects_pages() {
+        let store = MemoryObjectStore::new();
+        for index in 0..LIST_PAGE_SIZE + 1 {
+            store.insert("landing", &format!("finance/{:05}.txt", index), b"data");
+        }
+        store.insert("landing", "hr/00000.txt", b"data");
+        let newest = store.insert("landing", "finance/00000.txt", b"data");
+
+        let page = store
+            .list_objects("landing", "finance/", false, None, None)
+            .await
+            .unwrap();
+        assert_eq!(page.objects.len(), LIST_PAGE_SIZE as usize);
+        assert_eq!(page.next, Some(("finance/00999.txt".to_string(), None)));
+        let page = store
+            .list_objects(
+                "landing",
+                "finance/",
+                false,
+                Some("finance/00999.txt"),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(keys(&page), vec![("finance/01000.txt".to_string(), None)]);
+        assert_eq!(page.next, None);
+
+        // Every version, the newest first
+        let page = store
+            .list_objects("landing", "finance/00000", true, None, None)
+            .await
+            .unwrap();
+        assert_eq!(page.objects.len(), 2);
+        assert_eq!(page.objects[0].version_id, Some(newest));
+        // The older version is hidden by the newest one
+        let older = (
+            "finance/00000.txt".to_string(),
+            page.objects[1].version_id.clone().unwrap(),
+        );
+        assert_eq!(page.noncurrent, BTreeSet::from([older]));
+    }
+
+    #[tokio::test]
+    async fn test_injected_failures() {
+        let store = MemoryObjectStore::new();
+        store.insert("landing", "a.txt", b"data");
+        store.fail(StoreOperation::GetObject, 2, "SlowDown");
+        for _ in 0..2 {
+            let error = store.get_object("landing", "a.txt").await.unwrap_err();
+            assert_eq!(error.to_string(), "SlowDown");
+        }
+        assert!(store.get_object("landing", "a.txt").await.is_ok());
+        assert_eq!(store.take_calls().len(), 3);
+        assert!(store.calls().is_empty());
+    }
+}
@@ -0,0 +1,351 @@
+use crate::config::{Config, MessageFormat};
+use crate::{decoded_key, Outcome, ValidationIssue};
+use aws_lambda_events::s3::S3EventRecord;
+use aws_sdk_s3::model::Tagging;
+use chrono::{DateTime, Utc};
+use lambda_runtime::Error;
+use schemars::{schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+// The /// comments on the message types become the descriptions of the generated JSON Schema
+
+// Version of the message body layout, bumped whenever a field is added, renamed or removed
+pub const SCHEMA_VERSION: &str = "1.3";
+
+// CloudEvents specification implemented by the envelope
+pub const CLOUDEVENTS_SPEC_VERSION: &str = "1.0";
+
+/// The object version that was validated, taken from the S3 event record.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ObjectIdentification {
+    /// Name of the bucket holding the object.
+    pub bucket: String,
+    /// Key of the object, URL decoded.
+    pub key: String,
+    /// Version of the object that was validated.
+    pub version_id: Option<String>,
+    /// ETag of the object version as reported by the S3 event.
+    pub etag: Option<String>,
+    /// Size of the object version in bytes.
+    pub size: Option<i64>,
+    /// Time at which S3 emitted the event for the object version.
+    pub event_time: DateTime<Utc>,
+    /// Principal that uploaded the object.
+    pub principal_id: Option<String>,
+}
+
+impl ObjectIdentification {
+    pub fn from_record(record: &S3EventRecord) -> Result<ObjectIdentification, Error> {
+        let bucket = record
+            .s3
+            .bucket
+            .name
+            .as_ref()
+            .ok_or("Missing bucket name")?;
+        Ok(ObjectIdentification {
+            bucket: bucket.to_owned(),
+            key: decoded_key(&record.s3)?,
+            version_id: record.s3.object.version_id.to_owned(),
+            etag: record.s3.object.e_tag.to_owned(),
+            size: record.s3.object.size,
+            event_time: record.event_time,
+            principal_id: record.principal_id.principal_id.to_owned(),
+        })
+    }
+}
+
+/// Where the workflow copied the object version.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ObjectLocation {
+    pub bucket: String,
+    pub key: String,
+    /// Version of the copy, when the destination bucket is versioned.
+    pub version_id: Option<String>,
+}
+
+/// A tag applied to the object version.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct MessageTag {
+    pub key: String,
+    pub value: String,
+}
+
+impl MessageTag {
+    pub fn from_tagging(tagging: &Tagging) -> Vec<MessageTag> {
+        tagging
+            .tag_set()
+            .unwrap_or_default()
+            .iter()
+            .map(|tag| MessageTag {
+                key: tag.key().unwrap_or_default().to_string(),
+                value: tag.value().unwrap_or_default().to_string(),
+            })
+            .collect()
+    }
+}
+
+// Create a struct to generate the message body
+// Could add a don't Deserialize if null. to decrease the size of the message.
+/// Result of validating an object uploaded to the landing bucket.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct ValidationMessageBody {
+    /// Version of this schema, consumers should reject major versions they don't know.
+    pub schema_version: String,
+    /// Name of the workflow that produced the message.
+    pub workflow: String,
+    /// Request id of the Lambda invocation that validated the object.
+    pub exc_id: String,
+    /// Teams or systems the message is relevant to.
+    pub categories: Vec<String>,
+    /// Human readable result of the validation.
+    pub message: String,
+    /// The object version that was validated.
+    pub object: ObjectIdentification,
+    /// Tags on the object version once the validation finished.
+    pub tags: Vec<MessageTag>,
+    /// Link to let an invalid object continue through the workflow.
+    pub continue_url: Option<String>,
+    /// Link to reject an invalid object.
+    pub abort_url: Option<String>,
+    /// Where an invalid object was moved to, added in 1.1.
+    #[serde(default)]
+    pub quarantine_location: Option<ObjectLocation>,
+    /// Where a valid object was promoted to, added in 1.2.
+    #[serde(default)]
+    pub promoted_location: Option<ObjectLocation>,
+    /// Checks a valid object failed with a warning severity, added in 1.3.
+    #[serde(default)]
+    pub warnings: Vec<ValidationIssue>,
+}
+
+/// CloudEvents 1.0 envelope in structured JSON mode, the validation message or its claim check is carried in `data`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct CloudEvent<T> {
+    pub specversion: String,
+    /// Unique for each event sent by a given source.
+    pub id: String,
+    pub source: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// Key of the validated object.
+    pub subject: Option<String>,
+    pub time: DateTime<Utc>,
+    pub datacontenttype: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dataschema: Option<String>,
+    pub data: T,
+}
+
+impl CloudEvent<ValidationMessageBody> {
+    pub fn wrap(
+        config: &Config,
+        outcome: Outcome,
+        body: ValidationMessageBody,
+    ) -> CloudEvent<ValidationMessageBody> {
+        CloudEvent {
+            specversion: CLOUDEVENTS_SPEC_VERSION.to_string(),
+            id: format!(
+                "{}:{}:{}",
+                body.exc_id,
+                body.object.key,
+                body.object.version_id.as_deref().unwrap_or("null")
+            ),
+            source: config.cloudevents.source.clone(),
+            event_type: format!("{}.{}", config.cloudevents.type_prefix, outcome.as_str()),
+            subject: Some(body.object.key.clone()),
+            time: Utc::now(),
+            datacontenttype: "application/json".to_string(),
+            dataschema: config.cloudevents.dataschema.clone(),
+            data: body,
+        }
+    }
+}
+
+// Serialize the message body in the format selected in the config
+pub fn encode_message(
+    config: &Config,
+    outcome: Outcome,
+    body: ValidationMessageBody,
+) -> Result<String, Error> {
+    let encoded = match config.message_format {
+        MessageFormat::Plain => serde_json::to_string(&body)?,
+        MessageFormat::CloudEvents => {
+            serde_json::to_string(&CloudEvent::wrap(config, outcome, body))?
+        }
+    };
+    Ok(encoded)
+}
+
+// JSON Schema of the plain message body, committed to schemas/ for consumers
+pub fn message_json_schema() -> String {
+    serde_json::to_string_pretty(&schema_for!(ValidationMessageBody)).unwrap()
+}
+
+// JSON Schema of the message wrapped in a CloudEvents envelope
+pub fn cloudevent_json_schema() -> String {
+    serde_json::to_string_pretty(&schema_for!(CloudEvent<ValidationMessageBody>)).unwrap()
+}
+
+#[cfg(test)]
+mod tests_message_body {
+    use super::*;
+    use aws_sdk_s3::model::Tag;
+
+    const RECORD: &str = r#"{
+        "eventVersion": "2.1",
+        "eventSource": "aws:s3",
+        "awsRegion": "eu-west-1",
+        "eventTime": "2023-02-01T10:15:30.000Z",
+        "eventName": "ObjectCreated:Put",
+        "userIdentity": { "principalId": "AWS:AIDAEXAMPLE" },
+        "requestParameters": { "sourceIPAddress": "127.0.0.1" },
+        "responseElements": {},
+        "s3": {
+            "s3SchemaVersion": "1.0",
+            "configurationId": "validation",
+            "bucket": { "name": "landing", "ownerIdentity": { "principalId": "OWNER" }, "arn": "arn:aws:s3:::landing" },
+            "object": { "key": "finance/1234-5678+copy.txt", "size": 42, "eTag": "abc123", "versionId": "v1", "sequencer": "0A" }
+        }
+    }"#;
+
+    #[test]
+    fn test_object_identification_from_record() {
+        let record: S3EventRecord = serde_json::from_str(RECORD).unwrap();
+        let object = ObjectIdentification::from_record(&record).unwrap();
+        assert_eq!(object.bucket, "landing");
+        assert_eq!(object.key, "finance/1234-5678 copy.txt");
+        assert_eq!(object.version_id.as_deref(), Some("v1"));
+        assert_eq!(object.etag.as_deref(), Some("abc123"));
+        assert_eq!(object.size, Some(42));
+        assert_eq!(object.event_time.to_rfc3339(), "2023-02-01T10:15:30+00:00");
+        assert_eq!(object.principal_id.as_deref(), Some("AWS:AIDAEXAMPLE"));
+    }
+
+    fn body() -> ValidationMessageBody {
+        let record: S3EventRecord = serde_json::from_str(RECORD).unwrap();
+        ValidationMessageBody {
+            schema_version: SCHEMA_VERSION.to_string(),
+            workflow: "Validation_Workflow".to_string(),
+            exc_id: "request-1".to_string(),
+            categories: vec!["CD-TECH".to_string()],
+            message: "File is valid".to_string(),
+            object: ObjectIdentification::from_record(&record).unwrap(),
+            tags: vec![],
+            continue_url: None,
+            abort_url: None,
+            quarantine_location: None,
+            promoted_location: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_encode_plain_message() {
+        let config = Config::default();
+        let encoded = encode_message(&config, Outcome::Success, body()).unwrap();
+        let decoded: ValidationMessageBody = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded, body());
+    }
+
+    #[test]
+    fn test_encode_cloudevents_message() {
+        let config = Config {
+            message_format: MessageFormat::CloudEvents,
+            ..Config::default()
+        };
+        let encoded = encode_message(&config, Outcome::Failure, body()).unwrap();
+        let decoded: CloudEvent<ValidationMessageBody> = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(decoded.specversion, "1.0");
+        assert_eq!(decoded.id, "request-1:finance/1234-5678 copy.txt:v1");
+        assert_eq!(decoded.source, config.cloudevents.source);
+        assert_eq!(
+            decoded.event_type,
+            format!("{}.failure", config.cloudevents.type_prefix)
+        );
+        assert_eq!(
+            decoded.subject.as_deref(),
+            Some("finance/1234-5678 copy.txt")
+        );
+        assert_eq!(decoded.datacontenttype, "application/json");
+        assert_eq!(decoded.data, body());
+        let raw: serde_json::Value = serde_json::from_str(&encoded).unwrap();
+        assert!(raw.get("dataschema").is_none());
+    }
+
+    #[test]
+    fn test_message_tags_from_tagging() {
+        let tagging = Tagging::builder()
+            .set_tag_set(Some(vec![
+                Tag::builder().key("validated").value("true").build(),
+                Tag::builder().key("valid").value("true").build(),
+            ]))
+            .build();
+        assert_eq!(
+            MessageTag::from_tagging(&tagging),
+            vec![
+                MessageTag {
+                    key: "validated".to_string(),
+                    value: "true".to_string()
+                },
+                MessageTag {
+                    key: "valid".to_string(),
+                    value: "true".to_string()
+                },
+            ]
+        );
+        assert!(MessageTag::from_tagging(&Tagging::builder().build()).is_empty());
+    }
+}
+
+// The schemas in schemas/ are generated from the Rust types, run the tests with
+// UPDATE_SCHEMAS=1 to regenerate them after changing the message body.
+#[cfg(test)]
+mod tests_json_schema {
+    use super::*;
+
+    fn check_schema(path: &str, committed: &str, generated: String) {
+        if std::env::var("UPDATE_SCHEMAS").is_ok() {
+            std::fs::write(path, format!("{}\n", generated)).unwrap();
+            return;
+        }
+        assert_eq!(
+            committed.trim_end(),
+            generated,
+            "{} is out of date, run the tests with UPDATE_SCHEMAS=1",
+            path
+        );
+    }
+
+    #[test]
+    fn test_message_schema_is_up_to_date() {
+        check_schema(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/schemas/validation-message.schema.json"
+            ),
+            include_str!("../schemas/validation-message.schema.json"),
+            message_json_schema(),
+        );
+    }
+
+    #[test]
+    fn test_cloudevent_schema_is_up_to_date() {
+        check_schema(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/schemas/validation-message.cloudevent.schema.json"
+            ),
+            include_str!("../schemas/validation-message.cloudevent.schema.json"),
+            cloudevent_json_schema(),
+        );
+    }
+
+    #[test]
+    fn test_schema_version_matches_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&message_json_schema()).unwrap();
+        assert!(schema["required"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::Value::from("schema_version")));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::copy::copy_version;
+use crate::message::{ObjectIdentification, ObjectLocation};
+use crate::store::ObjectStore;
+use lambda_runtime::Error;
+use serde::Deserialize;
+use std::path::Path;
+
+// Placeholders of the key template of the curated location
+const PLACEHOLDERS: [&str; 11] = [
+    "bucket", "key", "prefix", "filename", "stem", "ext", "prodid", "yyyy", "mm", "dd", "hh",
+];
+
+// Copy valid objects to a curated location, disabled when not set
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PromotionConfig {
+    // Defaults to the bucket of the object
+    #[serde(default)]
+    pub bucket: Option<String>,
+    // Key of the copy, e.g. curated/{prodid}/{yyyy}/{mm}/{dd}/{filename}. The date is the
+    // time of the S3 event.
+    #[serde(default = "default_key_template")]
+    pub key_template: String,
+}
+
+fn default_key_template() -> String {
+    "curated/{prodid}/{yyyy}/{mm}/{dd}/{filename}".to_string()
+}
+
+impl PromotionConfig {
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        if self.bucket.as_deref() == Some("") {
+            error_messages.push("Promotion bucket must not be empty".to_string());
+        }
+        let mut rest = self.key_template.as_str();
+        while let Some(start) = rest.find('{') {
+            match rest[start..].find('}') {
+                Some(end) => {
+                    let name = &rest[start + 1..start + end];
+                    if !PLACEHOLDERS.contains(&name) {
+                        error_messages.push(format!(
+                            "Unknown placeholder {{{}}} in the promotion key template",
+                            name
+                        ));
+                    }
+                    rest = &rest[start + end + 1..];
+                }
+                None => {
+                    error_messages
+                        .push("Unclosed placeholder in the promotion key template".to_string());
+                    break;
+                }
+            }
+        }
+        // Without a fixed prefix the copies can't be told apart from new uploads
+        if self.bucket.is_none() && self.fixed_prefix().is_empty() {
+            error_messages.push(
+                "Promotion key template must start with a fixed prefix when promoting in place"
+                    .to_string(),
+            );
+        }
+        error_messages
+    }
+
+    // Text of the template before the first placeholder
+    fn fixed_prefix(&self) -> &str {
+        let end = self
+            .key_template
+            .find('{')
+            .unwrap_or(self.key_template.len());
+        &self.key_template[..end]
+    }
+
+    // Promoted objects must not be validated again
+    pub fn contains(&self, bucket: &str, key: &str) -> bool {
+        if let Some(expected) = &self.bucket {
+            if expected != bucket {
+                return false;
+            }
+        }
+        key.starts_with(self.fixed_prefix())
+    }
+
+    pub fn location_of(&self, object: &ObjectIdentification) -> (String, String) {
+        let path = Path::new(&object.key);
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        let values = [
+            ("bucket", object.bucket.clone()),
+            ("key", object.key.clone()),
+            (
+                "prefix",
+                crate::sqs::key_prefix(&object.key)
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            ("filename", file_name.to_string()),
+            ("stem", stem.to_string()),
+            (
+                "ext",
+                path.extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            // The Prod ID is the file name without its extension
+            ("prodid", stem.to_string()),
+            ("yyyy", object.event_time.format("%Y").to_string()),
+            ("mm", object.event_time.format("%m").to_string()),
+            ("dd", object.event_time.format("%d").to_string()),
+            ("hh", object.event_time.format("%H").to_string()),
+        ];
+        let key = values
+            .iter()
+            .fold(self.key_template.clone(), |key, (name, value)| {
+                key.replace(&format!("{{{}}}", name), value)
+            });
+        (
+            self.bucket.clone().unwrap_or_else(|| object.bucket.clone()),
+            key,
+        )
+    }
+}
+
+// Copy the validated version with its metadata and tags to the curated location
+pub async fn promote_object(
+    store: &dyn ObjectStore,
+    config: &PromotionConfig,
+    object: &ObjectIdentification,
+) -> Result<ObjectLocation, Error> {
+    let (bucket, key) = config.location_of(object);
+    copy_version(store, object, &bucket, &key).await
+}
+
+#[cfg(test)]
+mod tests_promotion_config {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn object() -> ObjectIdentification {
+        ObjectIdentification {
+            bucket: "landing".to_string(),
+            key: "finance/1234-5678-9012-3456.txt".to_string(),
+            version_id: Some("v1".to_string()),
+            etag: None,
+            size: Some(42),
+            event_time: Utc.with_ymd_and_hms(2023, 2, 1, 10, 15, 30).unwrap(),
+            principal_id: None,
+        }
+    }
+
+    #[test]
+    fn test_default_key_template() {
+        let config: PromotionConfig = serde_json::from_str("{}").unwrap();
+        assert!(config.check().is_empty());
+        assert_eq!(
+            config.location_of(&object()),
+            (
+                "landing".to_string(),
+                "curated/1234-5678-9012-3456/2023/02/01/1234-5678-9012-3456.txt".to_string()
+            )
+        );
+        assert!(config.contains("landing", "curated/1234/2023/02/01/1234.txt"));
+        assert!(!config.contains("landing", "finance/1234.txt"));
+    }
+
+    #[test]
+    fn test_key_template_placeholders() {
+        let config: PromotionConfig = serde_json::from_str(
+            r#"{ "bucket": "curated", "key_template": "{prefix}{yyyy}{mm}{dd}{hh}/{stem}.{ext}" }"#,
+        )
+        .unwrap();
+        assert!(config.check().is_empty());
+        assert_eq!(
+            config.location_of(&object()),
+            (
+                "curated".to_string(),
+                "finance/2023020110/1234-5678-9012-3456.txt".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_check_key_template() {
+        let config: PromotionConfig =
+            serde_json::from_str(r#"{ "key_template": "{product}/{filename" }"#).unwrap();
+        assert_eq!(
+            config.check(),
+            vec![
+                "Unknown placeholder {product} in the promotion key template",
+                "Unclosed placeholder in the promotion key template",
+                "Promotion key template must start with a fixed prefix when promoting in place",
+            ]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::copy::copy_version;
+use crate::message::{ObjectIdentification, ObjectLocation};
+use crate::store::ObjectStore;
+use lambda_runtime::Error;
+use serde::Deserialize;
+
+// What happens to the original object once it was copied to the quarantine location
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OriginalAction {
+    // Add a delete marker, the version stays in the bucket history
+    Tombstone,
+    // Permanently delete the version
+    Delete,
+}
+
+// Move invalid objects out of the way of the jobs listing the landing bucket, disabled when not set
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct QuarantineConfig {
+    // Defaults to the bucket of the object
+    #[serde(default)]
+    pub bucket: Option<String>,
+    // Prepended to the key of the object
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    #[serde(default = "default_original")]
+    pub original: OriginalAction,
+}
+
+fn default_prefix() -> String {
+    "quarantine/".to_string()
+}
+
+fn default_original() -> OriginalAction {
+    OriginalAction::Tombstone
+}
+
+impl QuarantineConfig {
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        if self.bucket.as_deref() == Some("") {
+            error_messages.push("Quarantine bucket must not be empty".to_string());
+        }
+        // The copy would overwrite the object it was made from
+        if self.bucket.is_none() && self.prefix.is_empty() {
+            error_messages
+                .push("Quarantine prefix is required when quarantining in place".to_string());
+        }
+        error_messages
+    }
+
+    // Objects moved to quarantine must not be validated again
+    pub fn contains(&self, bucket: &str, key: &str) -> bool {
+        if let Some(expected) = &self.bucket {
+            if expected != bucket {
+                return false;
+            }
+        }
+        key.starts_with(&self.prefix)
+    }
+
+    pub fn location_of(&self, object: &ObjectIdentification) -> (String, String) {
+        (
+            self.bucket.clone().unwrap_or_else(|| object.bucket.clone()),
+            format!("{}{}", self.prefix, object.key),
+        )
+    }
+}
+
+// Copy the version to the quarantine location with its tags and metadata, then remove the original
+pub async fn quarantine_object(
+    store: &dyn ObjectStore,
+    config: &QuarantineConfig,
+    object: &ObjectIdentification,
+) -> Result<ObjectLocation, Error> {
+    let (bucket, key) = config.location_of(object);
+    let location = copy_version(store, object, &bucket, &key).await?;
+
+    // A tombstone keeps the version behind a delete marker
+    let version_id = match config.original {
+        OriginalAction::Delete => object.version_id.as_deref(),
+        OriginalAction::Tombstone => None,
+    };
+    store
+        .delete_object(&object.bucket, &object.key, version_id)
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+            "Original Error: {}; Copied to s3://{}/{} but could not remove Object s3://{}/{} versionId: {}",
+            original_error,
+            location.bucket,
+            location.key,
+            object.bucket,
+            object.key,
+            object.version_id.as_deref().unwrap_or("null")
+        ))
+    })?;
+    Ok(location)
+}
+
+#[cfg(test)]
+mod tests_quarantine_config {
+    use super::*;
+    use chrono::Utc;
+
+    fn object() -> ObjectIdentification {
+        ObjectIdentification {
+            bucket: "landing".to_string(),
+            key: "finance/1234.txt".to_string(),
+            version_id: Some("v1".to_string()),
+            etag: None,
+            size: Some(42),
+            event_time: Utc::now(),
+            principal_id: None,
+        }
+    }
+
+    #[test]
+    fn test_quarantine_in_place() {
+        let config: QuarantineConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.original, OriginalAction::Tombstone);
+        assert!(config.check().is_empty());
+        assert_eq!(
+            config.location_of(&object()),
+            (
+                "landing".to_string(),
+                "quarantine/finance/1234.txt".to_string()
+            )
+        );
+        assert!(config.contains("landing", "quarantine/finance/1234.txt"));
+        assert!(config.contains("other", "quarantine/finance/1234.txt"));
+        assert!(!config.contains("landing", "finance/1234.txt"));
+    }
+
+    #[test]
+    fn test_quarantine_bucket() {
+        let config: QuarantineConfig = serde_json::from_str(
+            r#"{ "bucket": "quarantine", "prefix": "", "original": "delete" }"#,
+        )
+        .unwrap();
+        assert!(config.check().is_empty());
+        assert_eq!(
+            config.location_of(&object()),
+            ("quarantine".to_string(), "finance/1234.txt".to_string())
+        );
+        assert!(!config.contains("landing", "finance/1234.txt"));
+
+        let in_place = QuarantineConfig {
+            bucket: None,
+            ..config
+        };
+        assert_eq!(in_place.check().len(), 1);
+    }
+}
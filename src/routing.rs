@@ -0,0 +1,237 @@
+use crate::sink::Destination;
+use crate::Outcome;
+use serde::Deserialize;
+
+// A routing rule. Every criterion that is set must match for the route to apply,
+// an unset criterion matches anything.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Route {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub outcome: Option<Outcome>,
+    #[serde(default)]
+    pub error_code: Option<String>,
+    // Shorthand for SQS destinations
+    #[serde(default)]
+    pub queues: Vec<String>,
+    #[serde(default)]
+    pub destinations: Vec<Destination>,
+}
+
+impl Route {
+    fn all_destinations(&self) -> impl Iterator<Item = Destination> + '_ {
+        self.queues
+            .iter()
+            .map(|queue_url| Destination::sqs(queue_url))
+            .chain(self.destinations.iter().cloned())
+    }
+
+    fn matches(&self, key: &str, outcome: Outcome, error_codes: &[&str]) -> bool {
+        criteria_match(
+            self.prefix.as_deref(),
+            self.outcome,
+            self.error_code.as_deref(),
+            key,
+            outcome,
+            error_codes,
+        )
+    }
+}
+
+// The criteria shared by routes and category rules, an unset criterion matches anything
+pub(crate) fn criteria_match(
+    prefix: Option<&str>,
+    expected_outcome: Option<Outcome>,
+    error_code: Option<&str>,
+    key: &str,
+    outcome: Outcome,
+    error_codes: &[&str],
+) -> bool {
+    if let Some(prefix) = prefix {
+        if !key.starts_with(prefix) {
+            return false;
+        }
+    }
+    if let Some(expected) = expected_outcome {
+        if expected != outcome {
+            return false;
+        }
+    }
+    if let Some(code) = error_code {
+        if !error_codes.contains(&code) {
+            return false;
+        }
+    }
+    true
+}
+
+// Maps (key prefix, outcome, error code) to the destinations that should be notified.
+// All matching routes contribute their destinations, the default queue is used when none match.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(transparent)]
+pub struct RoutingTable {
+    pub routes: Vec<Route>,
+}
+
+impl RoutingTable {
+    pub fn destinations(
+        &self,
+        key: &str,
+        outcome: Outcome,
+        error_codes: &[&str],
+        default_queue: &str,
+    ) -> Vec<Destination> {
+        let mut destinations: Vec<Destination> = Vec::new();
+        for route in self
+            .routes
+            .iter()
+            .filter(|route| route.matches(key, outcome, error_codes))
+        {
+            for destination in route.all_destinations() {
+                if !destinations.contains(&destination) {
+                    destinations.push(destination);
+                }
+            }
+        }
+        if destinations.is_empty() {
+            destinations.push(Destination::sqs(default_queue));
+        }
+        destinations
+    }
+
+    // Report every route that could never deliver a message
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        for (index, route) in self.routes.iter().enumerate() {
+            if route.queues.is_empty() && route.destinations.is_empty() {
+                error_messages.push(format!("Route {} has no destinations", index));
+            }
+            if route.error_code.is_some() && route.outcome == Some(Outcome::Success) {
+                error_messages.push(format!(
+                    "Route {} matches an error code on success, it can never apply",
+                    index
+                ));
+            }
+            for destination in route.all_destinations() {
+                if let Some(error) = destination.check() {
+                    error_messages.push(format!("Route {}: {}", index, error));
+                }
+            }
+        }
+        error_messages
+    }
+}
+
+#[cfg(test)]
+mod tests_routing_table {
+    use super::*;
+
+    fn table() -> RoutingTable {
+        serde_json::from_str(
+            r#"[
+                { "prefix": "finance/", "outcome": "failure", "queues": ["https://sqs/finance.fifo"] },
+                { "error_code": "invalid_name_format", "queues": ["https://sqs/data-quality.fifo"] },
+                { "error_code": "non_numeric_name", "queues": ["https://sqs/data-quality.fifo"] }
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_no_matching_route_uses_default_queue() {
+        let table = table();
+        assert_eq!(
+            table.destinations("finance/1-2-3-4.txt", Outcome::Success, &[], "default"),
+            vec![Destination::sqs("default")]
+        );
+        assert_eq!(
+            table.destinations(
+                "ops/report.txt",
+                Outcome::Failure,
+                &["empty_file"],
+                "default"
+            ),
+            vec![Destination::sqs("default")]
+        );
+    }
+
+    #[test]
+    fn test_prefix_and_outcome_route() {
+        assert_eq!(
+            table().destinations(
+                "finance/a.txt",
+                Outcome::Failure,
+                &["empty_file"],
+                "default"
+            ),
+            vec![Destination::sqs("https://sqs/finance.fifo")]
+        );
+    }
+
+    #[test]
+    fn test_matching_routes_are_combined_without_duplicates() {
+        assert_eq!(
+            table().destinations(
+                "finance/a-b.txt",
+                Outcome::Failure,
+                &["invalid_name_format", "non_numeric_name"],
+                "default"
+            ),
+            vec![
+                Destination::sqs("https://sqs/finance.fifo"),
+                Destination::sqs("https://sqs/data-quality.fifo")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_route_with_sns_and_eventbridge_destinations() {
+        let table: RoutingTable = serde_json::from_str(
+            r#"[
+                {
+                    "prefix": "finance/",
+                    "queues": ["https://sqs/finance.fifo"],
+                    "destinations": [
+                        { "type": "sns", "topic_arn": "arn:aws:sns:eu-west-1:123456789012:finance" },
+                        { "type": "event_bridge", "event_bus": "finance" }
+                    ]
+                }
+            ]"#,
+        )
+        .unwrap();
+        let destinations = table.destinations("finance/a.txt", Outcome::Success, &[], "default");
+        assert_eq!(
+            destinations
+                .iter()
+                .map(|destination| destination.name())
+                .collect::<Vec<_>>(),
+            vec![
+                "https://sqs/finance.fifo",
+                "arn:aws:sns:eu-west-1:123456789012:finance",
+                "finance"
+            ]
+        );
+        assert!(table.check().is_empty());
+    }
+
+    #[test]
+    fn test_check_reports_unusable_routes() {
+        let table: RoutingTable = serde_json::from_str(
+            r#"[
+                { "queues": [] },
+                { "outcome": "success", "error_code": "empty_file", "queues": ["sqs/queue"] }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            table.check(),
+            vec![
+                "Route 0 has no destinations",
+                "Route 1 matches an error code on success, it can never apply",
+                "Route 1: Invalid queue URL: sqs/queue",
+            ]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{Severity, ValidationIssue, ValidationReport, ERROR_CODES};
+use aws_lambda_events::s3::S3Entity;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// The checks run against an object and their parameters. The default is the original policy:
+// a non empty .txt file named after a Prod ID, e.g. 1234-5678-9012-3456.txt.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleSet {
+    // Identifies the rule set in logs and divergence records
+    pub name: String,
+    // Allowed extensions without the dot, any extension is accepted when empty
+    pub extensions: Vec<String>,
+    pub min_size: i64,
+    pub max_size: Option<i64>,
+    // Number of dash separated parts of the file name, the name is not checked when not set
+    pub name_parts: Option<usize>,
+    // Every part of the file name must be numeric
+    pub numeric_name: bool,
+    // Severity of the checks keyed by error code, a check that is not listed is an error
+    pub severities: BTreeMap<String, Severity>,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            name: "default".to_string(),
+            extensions: vec!["txt".to_string()],
+            min_size: 1,
+            max_size: None,
+            name_parts: Some(4),
+            numeric_name: true,
+            severities: BTreeMap::new(),
+        }
+    }
+}
+
+impl RuleSet {
+    pub fn validate(&self, s3_entity: &S3Entity) -> ValidationReport {
+        let mut issues = Vec::new();
+
+        // Check the file extension
+        if let Some(issue) = self.check_file_extension(s3_entity) {
+            issues.push(issue);
+        }
+        // Check the file size
+        if let Some(issue) = self.check_file_size(s3_entity) {
+            issues.push(issue);
+        }
+        // Check the file name format
+        if let Some(issue) = self.check_file_name(s3_entity) {
+            issues.push(issue);
+        }
+
+        ValidationReport { issues }.with_severities(&self.severities)
+    }
+
+    fn check_file_extension(&self, s3_entity: &S3Entity) -> Option<ValidationIssue> {
+        if self.extensions.is_empty() {
+            return None;
+        }
+        // Get the key of the object
+        let key = match &s3_entity.object.key {
+            Some(k) => k,
+            None => return Some(ValidationIssue::new("missing_key", "Missing object key")),
+        };
+
+        // Get the file extension
+        let file_extension = match Path::new(key).extension() {
+            Some(ext) => ext.to_str().unwrap(),
+            None => {
+                return Some(ValidationIssue::new(
+                    "missing_extension",
+                    "Missing file extension",
+                ))
+            }
+        };
+
+        // Check if the file extension is one of the allowed ones
+        if !self
+            .extensions
+            .iter()
+            .any(|extension| extension.trim_start_matches('.') == file_extension)
+        {
+            let expected = self
+                .extensions
+                .iter()
+                .map(|extension| format!(".{}", extension.trim_start_matches('.')))
+                .collect::<Vec<String>>()
+                .join(" or ");
+            return Some(ValidationIssue::new(
+                "invalid_extension",
+                &format!("Invalid file extension, should be {}", expected),
+            ));
+        }
+        None
+    }
+
+    fn check_file_size(&self, s3_entity: &S3Entity) -> Option<ValidationIssue> {
+        // Get the size of the object
+        let size = match s3_entity.object.size {
+            Some(s) => s,
+            None => return Some(ValidationIssue::new("missing_size", "Missing object size")),
+        };
+
+        // Check if the file size is within the bounds
+        if size <= 0 && self.min_size > 0 {
+            return Some(ValidationIssue::new(
+                "empty_file",
+                "Invalid size, it should be greater than 0",
+            ));
+        }
+        if size < self.min_size {
+            return Some(ValidationIssue::new(
+                "file_too_small",
+                &format!(
+                    "Invalid size, it should be at least {} bytes",
+                    self.min_size
+                ),
+            ));
+        }
+        if let Some(max_size) = self.max_size {
+            if size > max_size {
+                return Some(ValidationIssue::new(
+                    "file_too_large",
+                    &format!("Invalid size, it should be at most {} bytes", max_size),
+                ));
+            }
+        }
+        None
+    }
+
+    fn check_file_name(&self, s3_entity: &S3Entity) -> Option<ValidationIssue> {
+        let name_parts = self.name_parts?;
+        let key = match &s3_entity.object.key {
+            Some(k) => k,
+            None => return Some(ValidationIssue::new("missing_key", "Missing object key")),
+        };
+        let invalid_name = || {
+            ValidationIssue::new(
+                "invalid_name_format",
+                "Invalid file name format, it should be formated as a Prod ID",
+            )
+        };
+        // Keys like ".." or "finance/.." have no file name
+        let file_name_without_ext = match Path::new(key).file_stem().and_then(|s| s.to_str()) {
+            Some(stem) => stem,
+            None => return Some(invalid_name()),
+        };
+        let parts: Vec<&str> = file_name_without_ext.split("-").collect();
+        if parts.len() != name_parts {
+            return Some(invalid_name());
+        }
+
+        if self.numeric_name {
+            for part in parts {
+                if !part.chars().all(|c| c.is_numeric()) {
+                    return Some(ValidationIssue::new(
+                        "non_numeric_name",
+                        "Invalid file name format, it should be a numeric code",
+                    ));
+                }
+            }
+        }
+        None
+    }
+
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        if self.name.trim().is_empty() {
+            error_messages.push("Rule set name must not be empty".to_string());
+        }
+        if self
+            .extensions
+            .iter()
+            .any(|extension| extension.trim_start_matches('.').is_empty())
+        {
+            error_messages.push("Extensions must not be empty".to_string());
+        }
+        if self.min_size < 0 {
+            error_messages.push("min_size must not be negative".to_string());
+        }
+        if self
+            .max_size
+            .is_some_and(|max_size| max_size < self.min_size)
+        {
+            error_messages.push("max_size must not be lower than min_size".to_string());
+        }
+        if self.name_parts == Some(0) {
+            error_messages.push("name_parts must be at least 1".to_string());
+        }
+        for code in self.severities.keys() {
+            if !ERROR_CODES.contains(&code.as_str()) {
+                error_messages.push(format!("Unknown error code {} in severities", code));
+            }
+        }
+        error_messages
+    }
+}
+
+#[cfg(test)]
+mod tests_rule_set {
+    use super::*;
+    use crate::testing::S3RecordBuilder;
+
+    fn codes(rules: &RuleSet, key: &str, size: i64) -> Vec<String> {
+        rules
+            .validate(&S3RecordBuilder::new("landing", key).size(size).entity())
+            .issues
+            .into_iter()
+            .map(|issue| issue.code)
+            .collect()
+    }
+
+    #[test]
+    fn test_default_rule_set() {
+        let rules = RuleSet::default();
+        assert!(codes(&rules, "1234-5678-9012-3456.txt", 42).is_empty());
+        assert_eq!(
+            codes(&rules, "report.csv", 0),
+            vec!["invalid_extension", "empty_file", "invalid_name_format"]
+        );
+        assert_eq!(
+            codes(&rules, "1234-5678-9012-ABCD.txt", 42),
+            vec!["non_numeric_name"]
+        );
+        // Keys without a file name fail the name check instead of panicking
+        for key in ["..", "finance/.."] {
+            assert!(codes(&rules, key, 42).contains(&"invalid_name_format".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_configured_rule_set() {
+        let rules: RuleSet = serde_json::from_str(
+            r#"{
+                "name": "v2",
+                "extensions": ["txt", ".csv"],
+                "min_size": 10,
+                "max_size": 100,
+                "name_parts": 2,
+                "numeric_name": false,
+                "severities": { "file_too_large": "warning" }
+            }"#,
+        )
+        .unwrap();
+        assert!(rules.check().is_empty());
+        assert!(codes(&rules, "sales-eu.csv", 42).is_empty());
+        assert_eq!(codes(&rules, "sales-eu.csv", 0), vec!["empty_file"]);
+        assert_eq!(
+            codes(&rules, "sales-eu.json", 5),
+            vec!["invalid_extension", "file_too_small"]
+        );
+
+        let report = rules.validate(
+            &S3RecordBuilder::new("landing", "sales.csv")
+                .size(500)
+                .entity(),
+        );
+        assert_eq!(
+            report.message(),
+            "Invalid file name format, it should be formated as a Prod ID"
+        );
+        assert_eq!(report.warnings()[0].code, "file_too_large");
+        assert_eq!(
+            codes(&rules, "sales-eu.json", 42),
+            vec!["invalid_extension"]
+        );
+        assert_eq!(
+            rules
+                .validate(
+                    &S3RecordBuilder::new("landing", "a-b.json")
+                        .size(42)
+                        .entity()
+                )
+                .issues[0]
+                .message,
+            "Invalid file extension, should be .txt or .csv"
+        );
+    }
+
+    #[test]
+    fn test_check_reports_every_problem() {
+        let rules = RuleSet {
+            name: " ".to_string(),
+            extensions: vec![".".to_string()],
+            min_size: 10,
+            max_size: Some(5),
+            name_parts: Some(0),
+            severities: BTreeMap::from([("empty_files".to_string(), Severity::Info)]),
+            ..RuleSet::default()
+        };
+        assert_eq!(
+            rules.check(),
+            vec![
+                "Rule set name must not be empty",
+                "Extensions must not be empty",
+                "max_size must not be lower than min_size",
+                "name_parts must be at least 1",
+                "Unknown error code empty_files in severities",
+            ]
+        );
+    }
+}
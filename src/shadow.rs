@@ -0,0 +1,154 @@
+use crate::message::ObjectIdentification;
+use crate::rules::RuleSet;
+use crate::{Outcome, ValidationReport};
+use serde::{Deserialize, Serialize};
+
+// A second rule set evaluated next to the primary one. It never tags an object nor changes the
+// notifications, its verdicts are only compared with the primary ones to measure the impact
+// of switching rule sets.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShadowConfig {
+    pub rules: RuleSet,
+    // Divergence records are sent to this queue too, they are only logged when not set
+    pub queue_url: Option<String>,
+}
+
+impl ShadowConfig {
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages: Vec<String> = self
+            .rules
+            .check()
+            .into_iter()
+            .map(|error| format!("Shadow rules: {}", error))
+            .collect();
+        if let Some(url) = &self.queue_url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                error_messages.push(format!(
+                    "Shadow queue URL is not a valid queue URL: {}",
+                    url
+                ));
+            }
+        }
+        error_messages
+    }
+}
+
+// The verdict of one rule set
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub rule_set: String,
+    pub outcome: Outcome,
+    pub report: ValidationReport,
+}
+
+impl Verdict {
+    pub fn new(rules: &RuleSet, report: &ValidationReport) -> Verdict {
+        Verdict {
+            rule_set: rules.name.clone(),
+            outcome: report.outcome(),
+            report: report.clone(),
+        }
+    }
+}
+
+// Emitted when the shadow rule set disagrees with the primary one about an object
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DivergenceRecord {
+    pub exc_id: String,
+    pub object: ObjectIdentification,
+    pub primary: Verdict,
+    pub shadow: Verdict,
+}
+
+impl DivergenceRecord {
+    // The verdicts differ when the outcome or the failed checks do, a warning that becomes
+    // an error is a divergence even if another error already failed the file
+    pub fn between(
+        exc_id: &str,
+        object: &ObjectIdentification,
+        primary: Verdict,
+        shadow: Verdict,
+    ) -> Option<DivergenceRecord> {
+        if primary.outcome == shadow.outcome
+            && primary.report.error_codes() == shadow.report.error_codes()
+        {
+            return None;
+        }
+        Some(DivergenceRecord {
+            exc_id: exc_id.to_string(),
+            object: object.clone(),
+            primary,
+            shadow,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests_shadow {
+    use super::*;
+    use crate::testing::S3RecordBuilder;
+
+    fn shadow_rules() -> RuleSet {
+        RuleSet {
+            name: "v2".to_string(),
+            extensions: vec!["txt".to_string(), "csv".to_string()],
+            name_parts: None,
+            ..RuleSet::default()
+        }
+    }
+
+    fn divergence(key: &str, size: i64) -> Option<DivergenceRecord> {
+        let record = S3RecordBuilder::new("landing", key).size(size);
+        let object = ObjectIdentification::from_record(&record.build()).unwrap();
+        let primary = RuleSet::default();
+        let shadow = shadow_rules();
+        DivergenceRecord::between(
+            "request-1",
+            &object,
+            Verdict::new(&primary, &primary.validate(&record.entity())),
+            Verdict::new(&shadow, &shadow.validate(&record.entity())),
+        )
+    }
+
+    #[test]
+    fn test_same_verdict_is_not_a_divergence() {
+        assert!(divergence("1234-5678-9012-3456.txt", 42).is_none());
+        assert!(divergence("1234-5678-9012-3456.json", 42).is_none());
+    }
+
+    #[test]
+    fn test_divergent_verdicts() {
+        let record = divergence("report.csv", 42).unwrap();
+        assert_eq!(record.exc_id, "request-1");
+        assert_eq!(record.object.key, "report.csv");
+        assert_eq!(record.primary.rule_set, "default");
+        assert_eq!(record.primary.outcome, Outcome::Failure);
+        assert_eq!(record.shadow.rule_set, "v2");
+        assert_eq!(record.shadow.outcome, Outcome::Success);
+
+        // Both fail, but not on the same checks
+        let record = divergence("report.csv", 0).unwrap();
+        assert_eq!(
+            record.primary.report.error_codes(),
+            vec!["invalid_extension", "empty_file", "invalid_name_format"]
+        );
+        assert_eq!(record.shadow.report.error_codes(), vec!["empty_file"]);
+    }
+
+    #[test]
+    fn test_check_prefixes_rule_set_errors() {
+        let shadow: ShadowConfig = serde_json::from_str(
+            r#"{ "rules": { "name": "", "min_size": -1 }, "queue_url": "sqs/divergence" }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            shadow.check(),
+            vec![
+                "Shadow rules: Rule set name must not be empty",
+                "Shadow rules: min_size must not be negative",
+                "Shadow queue URL is not a valid queue URL: sqs/divergence",
+            ]
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use crate::config::Config;
+use crate::dry_run::DryRunSink;
+use crate::sqs::{MessageQueue, OutboundMessage, Queue, SqsQueue};
+use crate::webhook::{WebhookSink, DEFAULT_TIMEOUT_MS};
+use async_trait::async_trait;
+use aws_sdk_eventbridge::model::PutEventsRequestEntry;
+use aws_sdk_eventbridge::Client as EventBridgeClient;
+use aws_sdk_sns::model::{MessageAttributeValue, PublishBatchRequestEntry};
+use aws_sdk_sns::Client as SnsClient;
+use aws_sdk_sqs::Client as SqsClient;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+// Where a notification is delivered, selected per route in the config file
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Destination {
+    Sqs {
+        queue_url: String,
+    },
+    Sns {
+        topic_arn: String,
+    },
+    EventBridge {
+        // Name or ARN of the event bus
+        event_bus: String,
+        #[serde(default)]
+        source: Option<String>,
+        #[serde(default)]
+        detail_type: Option<String>,
+    },
+    Webhook {
+        url: String,
+        // Environment variable holding the signing secret, kept out of the config file
+        secret_env: String,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+}
+
+impl Destination {
+    pub fn sqs(queue_url: &str) -> Destination {
+        Destination::Sqs {
+            queue_url: queue_url.to_string(),
+        }
+    }
+
+    // Identifies the destination in logs and responses
+    pub fn name(&self) -> &str {
+        match self {
+            Destination::Sqs { queue_url } => queue_url,
+            Destination::Sns { topic_arn } => topic_arn,
+            Destination::EventBridge { event_bus, .. } => event_bus,
+            Destination::Webhook { url, .. } => url,
+        }
+    }
+
+    pub fn check(&self) -> Option<String> {
+        match self {
+            Destination::Sqs { queue_url }
+                if !queue_url.starts_with("https://") && !queue_url.starts_with("http://") =>
+            {
+                Some(format!("Invalid queue URL: {}", queue_url))
+            }
+            Destination::Sns { topic_arn } if !topic_arn.starts_with("arn:aws:sns:") => {
+                Some(format!("Invalid SNS topic ARN: {}", topic_arn))
+            }
+            Destination::EventBridge { event_bus, .. } if event_bus.is_empty() => {
+                Some("EventBridge event bus must not be empty".to_string())
+            }
+            Destination::Webhook { url, .. }
+                if !url.starts_with("https://") && !url.starts_with("http://") =>
+            {
+                Some(format!("Invalid webhook URL: {}", url))
+            }
+            Destination::Webhook {
+                url, secret_env, ..
+            } if std::env::var(secret_env).map_or(true, |secret| secret.is_empty()) => {
+                Some(format!("Webhook {} has no secret in ${}", url, secret_env))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Outcome of delivering one message of a batch
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delivery {
+    // Id assigned to the message by the destination
+    Sent(String),
+    // Retryable failures are sent again by the MessageBuffer
+    Failed { error: String, retryable: bool },
+}
+
+// A channel notifications can be sent to. Implementations send a batch of at most
+// 10 messages and 256 KB and return one Delivery per message, in order.
+#[async_trait]
+pub trait NotificationSink: Send + Sync {
+    async fn send_batch(&self, messages: &[&OutboundMessage]) -> Vec<Delivery>;
+}
+
+// Every message of the batch failed the same way, e.g. the call itself was rejected
+pub(crate) fn failed_batch(len: usize, error: String) -> Vec<Delivery> {
+    vec![
+        Delivery::Failed {
+            error,
+            retryable: true,
+        };
+        len
+    ]
+}
+
+// Map the per-entry results of a batch API back to the messages, entries are identified
+// by their position in the batch.
+pub(crate) fn collect_deliveries<'a>(
+    len: usize,
+    successful: impl Iterator<Item = (Option<&'a str>, Option<&'a str>)>,
+    failed: impl Iterator<Item = (Option<&'a str>, String, bool)>,
+) -> Vec<Delivery> {
+    let mut deliveries = failed_batch(len, "Missing result for entry".to_string());
+    for (id, message_id) in successful {
+        if let Some(index) = id.and_then(|id| id.parse::<usize>().ok()) {
+            if index < len {
+                deliveries[index] = Delivery::Sent(message_id.unwrap_or_default().to_string());
+            }
+        }
+    }
+    for (id, error, sender_fault) in failed {
+        if let Some(index) = id.and_then(|id| id.parse::<usize>().ok()) {
+            if index < len {
+                deliveries[index] = Delivery::Failed {
+                    error,
+                    retryable: !sender_fault,
+                };
+            }
+        }
+    }
+    deliveries
+}
+
+pub struct SqsSink {
+    message_queue: Arc<dyn MessageQueue>,
+    queue: Queue,
+}
+
+#[async_trait]
+impl NotificationSink for SqsSink {
+    async fn send_batch(&self, messages: &[&OutboundMessage]) -> Vec<Delivery> {
+        self.message_queue
+            .send_message_batch(&self.queue, messages)
+            .await
+    }
+}
+
+pub struct SnsSink {
+    client: SnsClient,
+    topic_arn: String,
+}
+
+impl SnsSink {
+    // FIFO topic names always end with .fifo, like FIFO queues
+    fn is_fifo(&self) -> bool {
+        self.topic_arn.ends_with(".fifo")
+    }
+}
+
+#[async_trait]
+impl NotificationSink for SnsSink {
+    async fn send_batch(&self, messages: &[&OutboundMessage]) -> Vec<Delivery> {
+        let entries = messages
+            .iter()
+            .enumerate()
+            .map(|(id, message)| {
+                let attributes: HashMap<String, MessageAttributeValue> = message
+                    .attributes
+                    .iter()
+                    .map(|(name, value)| {
+                        (
+                            name.to_owned(),
+                            MessageAttributeValue::builder()
+                                .data_type("String")
+                                .string_value(value)
+                                .build(),
+                        )
+                    })
+                    .collect();
+                let mut entry = PublishBatchRequestEntry::builder()
+                    .id(id.to_string())
+                    .message(&message.body)
+                    .set_message_attributes(Some(attributes));
+                if self.is_fifo() {
+                    entry = entry
+                        .message_group_id(&message.group_id)
+                        .message_deduplication_id(&message.deduplication_id);
+                }
+                entry.build()
+            })
+            .collect();
+        let output = self
+            .client
+            .publish_batch()
+            .topic_arn(&self.topic_arn)
+            .set_publish_batch_request_entries(Some(entries))
+            .send()
+            .await;
+        match output {
+            Ok(output) => collect_deliveries(
+                messages.len(),
+                output
+                    .successful()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|entry| (entry.id(), entry.message_id())),
+                output.failed().unwrap_or_default().iter().map(|entry| {
+                    (
+                        entry.id(),
+                        format!(
+                            "{}: {}",
+                            entry.code().unwrap_or_default(),
+                            entry.message().unwrap_or_default()
+                        ),
+                        entry.sender_fault(),
+                    )
+                }),
+            ),
+            Err(e) => failed_batch(
+                messages.len(),
+                format!(
+                    "Original Error: {}; Could not publish batch to topic {}",
+                    e.into_service_error(),
+                    self.topic_arn
+                ),
+            ),
+        }
+    }
+}
+
+pub struct EventBridgeSink {
+    client: EventBridgeClient,
+    event_bus: String,
+    source: String,
+    detail_type: String,
+}
+
+#[async_trait]
+impl NotificationSink for EventBridgeSink {
+    async fn send_batch(&self, messages: &[&OutboundMessage]) -> Vec<Delivery> {
+        // The message body becomes the event detail, EventBridge has no message attributes
+        let entries = messages
+            .iter()
+            .map(|message| {
+                PutEventsRequestEntry::builder()
+                    .event_bus_name(&self.event_bus)
+                    .source(&self.source)
+                    .detail_type(&self.detail_type)
+                    .detail(&message.body)
+                    .set_trace_header(message.trace_header.clone())
+                    .build()
+            })
+            .collect();
+        let output = self
+            .client
+            .put_events()
+            .set_entries(Some(entries))
+            .send()
+            .await;
+        match output {
+            // Result entries are in the same order as the request entries
+            Ok(output) => {
+                let mut deliveries: Vec<Delivery> = output
+                    .entries()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|entry| match entry.error_code() {
+                        None => Delivery::Sent(entry.event_id().unwrap_or_default().to_string()),
+                        Some(code) => Delivery::Failed {
+                            error: format!(
+                                "{}: {}",
+                                code,
+                                entry.error_message().unwrap_or_default()
+                            ),
+                            retryable: code == "InternalFailure" || code == "ThrottlingException",
+                        },
+                    })
+                    .collect();
+                deliveries.resize(
+                    messages.len(),
+                    Delivery::Failed {
+                        error: "Missing result for entry".to_string(),
+                        retryable: true,
+                    },
+                );
+                deliveries
+            }
+            Err(e) => failed_batch(
+                messages.len(),
+                format!(
+                    "Original Error: {}; Could not put events to event bus {}",
+                    e.into_service_error(),
+                    self.event_bus
+                ),
+            ),
+        }
+    }
+}
+
+// Holds the clients of every channel, created once at cold start, and builds the sink of a destination
+#[derive(Clone)]
+pub struct Notifier {
+    pub message_queue: Arc<dyn MessageQueue>,
+    pub sns_client: SnsClient,
+    pub eventbridge_client: EventBridgeClient,
+    pub http_client: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn new(sdk_config: &aws_config::SdkConfig) -> Notifier {
+        Notifier {
+            message_queue: Arc::new(SqsQueue::new(SqsClient::new(sdk_config))),
+            sns_client: SnsClient::new(sdk_config),
+            eventbridge_client: EventBridgeClient::new(sdk_config),
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    // Send the SQS messages through another queue implementation, e.g. MemoryMessageQueue in tests
+    pub fn with_message_queue(self, message_queue: Arc<dyn MessageQueue>) -> Notifier {
+        Notifier {
+            message_queue,
+            ..self
+        }
+    }
+
+    pub fn sink(&self, config: &Config, destination: &Destination) -> Box<dyn NotificationSink> {
+        // Nothing is delivered in dry-run mode, whatever the destination
+        if config.dry_run {
+            return Box::new(DryRunSink {
+                destination: destination.name().to_string(),
+            });
+        }
+        match destination {
+            Destination::Sqs { queue_url } => Box::new(SqsSink {
+                message_queue: self.message_queue.clone(),
+                queue: config.queue(queue_url),
+            }),
+            Destination::Sns { topic_arn } => Box::new(SnsSink {
+                client: self.sns_client.clone(),
+                topic_arn: topic_arn.clone(),
+            }),
+            Destination::EventBridge {
+                event_bus,
+                source,
+                detail_type,
+            } => Box::new(EventBridgeSink {
+                client: self.eventbridge_client.clone(),
+                event_bus: event_bus.clone(),
+                source: source
+                    .clone()
+                    .unwrap_or_else(|| config.cloudevents.source.clone()),
+                detail_type: detail_type
+                    .clone()
+                    .unwrap_or_else(|| "Validation Result".to_string()),
+            }),
+            Destination::Webhook {
+                url,
+                secret_env,
+                timeout_ms,
+            } => Box::new(WebhookSink {
+                client: self.http_client.clone(),
+                url: url.clone(),
+                // The secret was checked when the config was loaded
+                secret: std::env::var(secret_env).unwrap_or_default(),
+                timeout: Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_destinations {
+    use super::*;
+
+    #[test]
+    fn test_destination_from_config() {
+        let destinations: Vec<Destination> = serde_json::from_str(
+            r#"[
+                { "type": "sqs", "queue_url": "https://sqs/a.fifo" },
+                { "type": "sns", "topic_arn": "arn:aws:sns:eu-west-1:123456789012:finance" },
+                { "type": "event_bridge", "event_bus": "default", "detail_type": "File Validated" }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(destinations[0], Destination::sqs("https://sqs/a.fifo"));
+        assert_eq!(
+            destinations[1].name(),
+            "arn:aws:sns:eu-west-1:123456789012:finance"
+        );
+        assert_eq!(
+            destinations[2],
+            Destination::EventBridge {
+                event_bus: "default".to_string(),
+                source: None,
+                detail_type: Some("File Validated".to_string()),
+            }
+        );
+        assert!(destinations.iter().all(|d| d.check().is_none()));
+    }
+
+    #[test]
+    fn test_destination_check() {
+        assert!(Destination::sqs("sqs/a").check().is_some());
+        let topic = Destination::Sns {
+            topic_arn: "finance".to_string(),
+        };
+        assert_eq!(
+            topic.check(),
+            Some("Invalid SNS topic ARN: finance".to_string())
+        );
+        let webhook = Destination::Webhook {
+            url: "https://partner.example.com/hook".to_string(),
+            secret_env: "TEST_UNSET_WEBHOOK_SECRET".to_string(),
+            timeout_ms: None,
+        };
+        assert_eq!(
+            webhook.check(),
+            Some(
+                "Webhook https://partner.example.com/hook has no secret in $TEST_UNSET_WEBHOOK_SECRET"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_collect_deliveries_by_entry_id() {
+        let deliveries = collect_deliveries(
+            3,
+            vec![(Some("2"), Some("m2")), (Some("0"), Some("m0"))].into_iter(),
+            vec![(Some("1"), "InvalidParameterValue: bad".to_string(), true)].into_iter(),
+        );
+        assert_eq!(
+            deliveries,
+            vec![
+                Delivery::Sent("m0".to_string()),
+                Delivery::Failed {
+                    error: "InvalidParameterValue: bad".to_string(),
+                    retryable: false
+                },
+                Delivery::Sent("m2".to_string()),
+            ]
+        );
+    }
+}
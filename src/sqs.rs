@@ -0,0 +1,518 @@
+use crate::config::{Config, MessageGroupStrategy, MAX_MESSAGE_GROUP_ID_LENGTH};
+use crate::message::{ObjectIdentification, SCHEMA_VERSION};
+use crate::sink::{collect_deliveries, failed_batch, Delivery};
+use crate::ValidationReport;
+use async_trait::async_trait;
+use aws_sdk_sqs::model::{
+    MessageAttributeValue, MessageSystemAttributeNameForSends, MessageSystemAttributeValue,
+    SendMessageBatchRequestEntry,
+};
+use aws_sdk_sqs::output::SendMessageOutput;
+use aws_sdk_sqs::Client as SqsClient;
+use lambda_runtime::Error;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+// SQS accepts a per-message delay of up to 15 minutes on standard queues
+pub const MAX_DELAY_SECONDS: i32 = 900;
+
+// Per queue overrides, keyed by queue URL in the config file
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct QueueSettings {
+    // Overrides the detection from the .fifo suffix of the queue URL
+    #[serde(default)]
+    pub fifo: Option<bool>,
+    // Delay before the message becomes visible, standard queues only
+    #[serde(default)]
+    pub delay_seconds: Option<i32>,
+}
+
+// A destination queue with its resolved settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct Queue {
+    pub url: String,
+    pub fifo: bool,
+    pub delay_seconds: Option<i32>,
+}
+
+impl Queue {
+    pub fn new(url: &str, settings: Option<&QueueSettings>) -> Queue {
+        let settings = settings.cloned().unwrap_or_default();
+        Queue {
+            url: url.to_string(),
+            // FIFO queue names always end with .fifo
+            fifo: settings.fifo.unwrap_or_else(|| url.ends_with(".fifo")),
+            delay_seconds: settings.delay_seconds,
+        }
+    }
+
+    pub fn check(&self) -> Option<String> {
+        match self.delay_seconds {
+            Some(_) if self.fifo => Some(format!(
+                "Queue {} is FIFO, delay_seconds is only supported on standard queues",
+                self.url
+            )),
+            Some(delay) if !(0..=MAX_DELAY_SECONDS).contains(&delay) => Some(format!(
+                "Queue {} delay_seconds must be between 0 and {}",
+                self.url, MAX_DELAY_SECONDS
+            )),
+            _ => None,
+        }
+    }
+}
+
+// A message ready to be sent: the encoded body plus the attributes consumers can
+// filter and route on without parsing the body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboundMessage {
+    pub body: String,
+    pub attributes: BTreeMap<String, String>,
+    // X-Ray trace header, forwarded as the AWSTraceHeader system attribute
+    pub trace_header: Option<String>,
+    pub group_id: String,
+    // Derived from the object version and outcome, so a retried invocation is deduplicated
+    pub deduplication_id: String,
+}
+
+impl OutboundMessage {
+    pub fn new(
+        config: &Config,
+        body: String,
+        object: &ObjectIdentification,
+        report: &ValidationReport,
+        trace_header: Option<String>,
+    ) -> OutboundMessage {
+        let mut attributes = BTreeMap::new();
+        let outcome = report.outcome().as_str();
+        attributes.insert("outcome".to_string(), outcome.to_string());
+        // SQS rejects empty attribute values, so attributes without a value are left out
+        if !report.is_valid() {
+            attributes.insert("error_codes".to_string(), report.error_codes().join(","));
+        }
+        attributes.insert("bucket".to_string(), object.bucket.clone());
+        if let Some(prefix) = key_prefix(&object.key) {
+            attributes.insert("prefix".to_string(), prefix.to_string());
+        }
+        attributes.insert("schema_version".to_string(), SCHEMA_VERSION.to_string());
+        OutboundMessage {
+            body,
+            attributes,
+            trace_header,
+            group_id: message_group_id(config, object),
+            deduplication_id: deduplication_id(object, outcome),
+        }
+    }
+}
+
+// Group ids and deduplication ids are limited to 128 printable ASCII characters
+pub fn is_valid_message_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_MESSAGE_GROUP_ID_LENGTH
+        && id.chars().all(|c| c.is_ascii_graphic())
+}
+
+fn sha256_hex(input: &str) -> String {
+    hex::encode(Sha256::digest(input.as_bytes()))
+}
+
+// Pick the FIFO group of the message, ids that SQS would reject (unicode, spaces,
+// over 128 characters) are replaced by their SHA-256 so the grouping is kept.
+pub fn message_group_id(config: &Config, object: &ObjectIdentification) -> String {
+    let group = match config.message_group_strategy {
+        MessageGroupStrategy::Fixed => None,
+        MessageGroupStrategy::Key => Some(object.key.as_str()),
+        MessageGroupStrategy::Prefix => key_prefix(&object.key),
+        MessageGroupStrategy::ProductId => Path::new(&object.key)
+            .file_stem()
+            .and_then(|stem| stem.to_str()),
+    };
+    match group {
+        None => config.message_group_id.clone(),
+        Some(group) if is_valid_message_id(group) => group.to_string(),
+        Some(group) => sha256_hex(group),
+    }
+}
+
+// Deduplication id for the result of validating an object version
+pub fn deduplication_id(object: &ObjectIdentification, outcome: &str) -> String {
+    sha256_hex(&format!(
+        "{}\n{}\n{}\n{}",
+        object.bucket,
+        object.key,
+        object.version_id.as_deref().unwrap_or_default(),
+        outcome
+    ))
+}
+
+// Everything up to and including the last / of the key, None for keys at the bucket root
+pub fn key_prefix(key: &str) -> Option<&str> {
+    key.rfind('/').map(|index| &key[..=index])
+}
+
+// Attributes of the message in the form expected by SendMessage and SendMessageBatch
+pub(crate) fn message_attributes(
+    message: &OutboundMessage,
+) -> HashMap<String, MessageAttributeValue> {
+    message
+        .attributes
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_owned(),
+                MessageAttributeValue::builder()
+                    .data_type("String")
+                    .string_value(value)
+                    .build(),
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn message_system_attributes(
+    message: &OutboundMessage,
+) -> Option<HashMap<MessageSystemAttributeNameForSends, MessageSystemAttributeValue>> {
+    message.trace_header.as_ref().map(|trace_header| {
+        HashMap::from([(
+            MessageSystemAttributeNameForSends::AwsTraceHeader,
+            MessageSystemAttributeValue::builder()
+                .data_type("String")
+                .string_value(trace_header)
+                .build(),
+        )])
+    })
+}
+
+// Size SQS counts against the 256 KB limit: the body plus every attribute name, type and value
+pub fn message_size(message: &OutboundMessage) -> usize {
+    let attributes: usize = message
+        .attributes
+        .iter()
+        .map(|(name, value)| name.len() + "String".len() + value.len())
+        .sum();
+    message.body.len() + attributes
+}
+
+// Send a message, FIFO-only parameters are left out for standard queues as SQS rejects them
+pub async fn send_message(
+    sqs_client: &SqsClient,
+    queue: &Queue,
+    message: &OutboundMessage,
+) -> Result<SendMessageOutput, Error> {
+    let queue_url = &queue.url;
+    let mut request = sqs_client
+        .send_message()
+        .queue_url(queue_url)
+        .message_body(&message.body);
+    if queue.fifo {
+        request = request
+            .message_group_id(&message.group_id)
+            .message_deduplication_id(&message.deduplication_id);
+    } else if let Some(delay_seconds) = queue.delay_seconds {
+        request = request.delay_seconds(delay_seconds);
+    }
+    let output = request
+        .set_message_attributes(Some(message_attributes(message)))
+        .set_message_system_attributes(message_system_attributes(message))
+        .send()
+        .await
+        .map_err(|e| {
+            let original_error = e.into_service_error().to_string();
+            Error::from(format!(
+                "Original Error: {}; Could not send message to queue {}",
+                original_error, queue_url
+            ))
+        })?;
+    Ok(output)
+}
+
+// The SQS operations of the notifications, so they can be recorded in tests
+#[async_trait]
+pub trait MessageQueue: Send + Sync {
+    // Returns the id SQS assigned to the message
+    async fn send_message(&self, queue: &Queue, message: &OutboundMessage)
+        -> Result<String, Error>;
+
+    // At most 10 messages, one Delivery per message in order
+    async fn send_message_batch(
+        &self,
+        queue: &Queue,
+        messages: &[&OutboundMessage],
+    ) -> Vec<Delivery>;
+}
+
+pub struct SqsQueue {
+    client: SqsClient,
+}
+
+impl SqsQueue {
+    pub fn new(client: SqsClient) -> SqsQueue {
+        SqsQueue { client }
+    }
+}
+
+#[async_trait]
+impl MessageQueue for SqsQueue {
+    async fn send_message(
+        &self,
+        queue: &Queue,
+        message: &OutboundMessage,
+    ) -> Result<String, Error> {
+        let output = send_message(&self.client, queue, message).await?;
+        Ok(output.message_id().unwrap_or_default().to_string())
+    }
+
+    async fn send_message_batch(
+        &self,
+        queue: &Queue,
+        messages: &[&OutboundMessage],
+    ) -> Vec<Delivery> {
+        let entries = messages
+            .iter()
+            .enumerate()
+            .map(|(id, message)| {
+                let mut entry = SendMessageBatchRequestEntry::builder()
+                    .id(id.to_string())
+                    .message_body(&message.body)
+                    .set_message_attributes(Some(message_attributes(message)))
+                    .set_message_system_attributes(message_system_attributes(message));
+                // FIFO-only parameters are left out for standard queues as SQS rejects them
+                if queue.fifo {
+                    entry = entry
+                        .message_group_id(&message.group_id)
+                        .message_deduplication_id(&message.deduplication_id);
+                } else if let Some(delay_seconds) = queue.delay_seconds {
+                    entry = entry.delay_seconds(delay_seconds);
+                }
+                entry.build()
+            })
+            .collect();
+        let output = self
+            .client
+            .send_message_batch()
+            .queue_url(&queue.url)
+            .set_entries(Some(entries))
+            .send()
+            .await;
+        match output {
+            Ok(output) => collect_deliveries(
+                messages.len(),
+                output
+                    .successful()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|entry| (entry.id(), entry.message_id())),
+                output.failed().unwrap_or_default().iter().map(|entry| {
+                    (
+                        entry.id(),
+                        format!(
+                            "{}: {}",
+                            entry.code().unwrap_or_default(),
+                            entry.message().unwrap_or_default()
+                        ),
+                        entry.sender_fault(),
+                    )
+                }),
+            ),
+            Err(e) => failed_batch(
+                messages.len(),
+                format!(
+                    "Original Error: {}; Could not send message batch to queue {}",
+                    e.into_service_error(),
+                    queue.url
+                ),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests_message_attributes {
+    use super::*;
+    use crate::ValidationIssue;
+    use chrono::Utc;
+
+    fn object(key: &str) -> ObjectIdentification {
+        ObjectIdentification {
+            bucket: "landing".to_string(),
+            key: key.to_string(),
+            version_id: Some("v1".to_string()),
+            etag: None,
+            size: Some(42),
+            event_time: Utc::now(),
+            principal_id: None,
+        }
+    }
+
+    #[test]
+    fn test_attributes_for_valid_file() {
+        let message = OutboundMessage::new(
+            &Config::default(),
+            "{}".to_string(),
+            &object("finance/2023/1234-5678-9012-3456.txt"),
+            &ValidationReport::default(),
+            Some("Root=1-5759e988-bd862e3fe1be46a994272793".to_string()),
+        );
+        let expected: BTreeMap<String, String> = [
+            ("outcome", "success"),
+            ("bucket", "landing"),
+            ("prefix", "finance/2023/"),
+            ("schema_version", SCHEMA_VERSION),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert_eq!(message.attributes, expected);
+        assert_eq!(
+            message.trace_header.as_deref(),
+            Some("Root=1-5759e988-bd862e3fe1be46a994272793")
+        );
+    }
+
+    #[test]
+    fn test_attributes_for_invalid_file_at_bucket_root() {
+        let report = ValidationReport {
+            issues: vec![
+                ValidationIssue::new(
+                    "invalid_extension",
+                    "Invalid file extension, should be .txt",
+                ),
+                ValidationIssue::new("empty_file", "Invalid size, it should be greater than 0"),
+            ],
+        };
+        let message = OutboundMessage::new(
+            &Config::default(),
+            "{}".to_string(),
+            &object("a.csv"),
+            &report,
+            None,
+        );
+        assert_eq!(message.attributes["outcome"], "failure");
+        assert_eq!(
+            message.attributes["error_codes"],
+            "invalid_extension,empty_file"
+        );
+        assert!(!message.attributes.contains_key("prefix"));
+        assert_eq!(message.trace_header, None);
+    }
+}
+
+#[cfg(test)]
+mod tests_message_ids {
+    use super::*;
+    use chrono::Utc;
+
+    fn object(key: &str, version_id: &str) -> ObjectIdentification {
+        ObjectIdentification {
+            bucket: "landing".to_string(),
+            key: key.to_string(),
+            version_id: Some(version_id.to_string()),
+            etag: None,
+            size: Some(42),
+            event_time: Utc::now(),
+            principal_id: None,
+        }
+    }
+
+    fn config(strategy: MessageGroupStrategy) -> Config {
+        Config {
+            message_group_strategy: strategy,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_group_id_strategies() {
+        let object = object("finance/2023/1234-5678-9012-3456.txt", "v1");
+        assert_eq!(
+            message_group_id(&config(MessageGroupStrategy::Fixed), &object),
+            "ValidationGroup"
+        );
+        assert_eq!(
+            message_group_id(&config(MessageGroupStrategy::Key), &object),
+            "finance/2023/1234-5678-9012-3456.txt"
+        );
+        assert_eq!(
+            message_group_id(&config(MessageGroupStrategy::Prefix), &object),
+            "finance/2023/"
+        );
+        assert_eq!(
+            message_group_id(&config(MessageGroupStrategy::ProductId), &object),
+            "1234-5678-9012-3456"
+        );
+    }
+
+    #[test]
+    fn test_group_id_falls_back_for_root_objects_and_hashes_invalid_ids() {
+        assert_eq!(
+            message_group_id(
+                &config(MessageGroupStrategy::Prefix),
+                &object("1234-5678-9012-3456.txt", "v1")
+            ),
+            "ValidationGroup"
+        );
+        let group_id = message_group_id(
+            &config(MessageGroupStrategy::Key),
+            &object("finance/monthly report.txt", "v1"),
+        );
+        assert_eq!(group_id.len(), 64);
+        assert!(is_valid_message_id(&group_id));
+    }
+
+    #[test]
+    fn test_deduplication_id_is_stable_per_version_and_outcome() {
+        let first = deduplication_id(&object("a.txt", "v1"), "success");
+        assert_eq!(first, deduplication_id(&object("a.txt", "v1"), "success"));
+        assert_ne!(first, deduplication_id(&object("a.txt", "v2"), "success"));
+        assert_ne!(first, deduplication_id(&object("a.txt", "v1"), "failure"));
+        assert!(is_valid_message_id(&first));
+    }
+}
+
+#[cfg(test)]
+mod tests_queue_settings {
+    use super::*;
+
+    #[test]
+    fn test_fifo_detection_from_url() {
+        let fifo = Queue::new("https://sqs/SuccessQueue.fifo", None);
+        assert!(fifo.fifo);
+        let standard = Queue::new("https://sqs/SuccessQueue", None);
+        assert!(!standard.fifo);
+        assert_eq!(standard.delay_seconds, None);
+    }
+
+    #[test]
+    fn test_settings_override_detection() {
+        let settings = QueueSettings {
+            fifo: Some(false),
+            delay_seconds: Some(60),
+        };
+        let queue = Queue::new("https://sqs/Proxy.fifo", Some(&settings));
+        assert!(!queue.fifo);
+        assert_eq!(queue.delay_seconds, Some(60));
+        assert_eq!(queue.check(), None);
+    }
+
+    #[test]
+    fn test_check_rejects_invalid_delays() {
+        let delayed = QueueSettings {
+            fifo: None,
+            delay_seconds: Some(60),
+        };
+        assert!(Queue::new("https://sqs/Failure.fifo", Some(&delayed))
+            .check()
+            .unwrap()
+            .contains("only supported on standard queues"));
+        let too_long = QueueSettings {
+            fifo: None,
+            delay_seconds: Some(901),
+        };
+        assert!(Queue::new("https://sqs/Failure", Some(&too_long))
+            .check()
+            .unwrap()
+            .contains("must be between 0 and 900"));
+    }
+}
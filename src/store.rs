@@ -0,0 +1,334 @@
+use crate::copy::copy_object_version;
+use crate::message::{ObjectIdentification, ObjectLocation};
+use async_trait::async_trait;
+use aws_sdk_s3::model::Tagging;
+use aws_sdk_s3::types::{ByteStream, DateTime};
+use aws_sdk_s3::Client as S3Client;
+use chrono::{TimeZone, Utc};
+use lambda_runtime::Error;
+use std::collections::BTreeSet;
+
+// Objects returned by a call of ObjectStore::list_objects
+pub const LIST_PAGE_SIZE: i32 = 1000;
+
+// What the workflow needs to know about an object version without reading it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjectHead {
+    pub version_id: Option<String>,
+    pub e_tag: Option<String>,
+    pub content_length: i64,
+}
+
+// A page of a listing and the markers the next page starts after
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjectPage {
+    pub objects: Vec<ObjectIdentification>,
+    // When listing every version, the key and version id of the versions that are not the
+    // current object of their key, because a newer version or a delete marker hides them
+    pub noncurrent: BTreeSet<(String, String)>,
+    pub next: Option<(String, Option<String>)>,
+}
+
+// The S3 operations of the workflow. A version id of None is the latest version of the object.
+// The errors carry the message of the service, the callers add what they were doing.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    async fn get_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<Tagging, Error>;
+
+    async fn put_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        tagging: Tagging,
+    ) -> Result<(), Error>;
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<ObjectHead, Error>;
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<Vec<u8>, Error>;
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<(), Error>;
+
+    // Copy the exact version of an object with its metadata and tags
+    async fn copy_object(
+        &self,
+        source: &ObjectIdentification,
+        bucket: &str,
+        key: &str,
+    ) -> Result<ObjectLocation, Error>;
+
+    // Without a version id, a delete marker hides the object in a versioned bucket
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<(), Error>;
+
+    // The latest version of every key under the prefix, or every version, in key order.
+    // The page starts after the markers, the upload time stands in for the event time.
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        all_versions: bool,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+    ) -> Result<ObjectPage, Error>;
+}
+
+// The ObjectStore of the Lambda, backed by S3
+#[derive(Clone, Debug)]
+pub struct S3Store {
+    pub client: S3Client,
+}
+
+impl S3Store {
+    pub fn new(client: S3Client) -> S3Store {
+        S3Store { client }
+    }
+}
+
+fn upload_time(last_modified: Option<&DateTime>) -> chrono::DateTime<Utc> {
+    last_modified
+        .and_then(|time| Utc.timestamp_opt(time.secs(), time.subsec_nanos()).single())
+        .unwrap_or_else(Utc::now)
+}
+
+#[async_trait]
+impl ObjectStore for S3Store {
+    async fn get_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<Tagging, Error> {
+        let output = self
+            .client
+            .get_object_tagging()
+            .bucket(bucket)
+            .key(key)
+            .set_version_id(version_id.map(str::to_string))
+            .send()
+            .await
+            .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+        Ok(Tagging::builder()
+            .set_tag_set(output.tag_set().map(|tags| tags.to_vec()))
+            .build())
+    }
+
+    async fn put_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        tagging: Tagging,
+    ) -> Result<(), Error> {
+        self.client
+            .put_object_tagging()
+            .bucket(bucket)
+            .key(key)
+            .set_version_id(version_id.map(str::to_string))
+            .tagging(tagging)
+            .send()
+            .await
+            .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+        Ok(())
+    }
+
+    async fn head_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<ObjectHead, Error> {
+        let output = self
+            .client
+            .head_object()
+            .bucket(bucket)
+            .key(key)
+            .set_version_id(version_id.map(str::to_string))
+            .send()
+            .await
+            .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+        Ok(ObjectHead {
+            version_id: output.version_id().map(str::to_string),
+            e_tag: output.e_tag().map(str::to_string),
+            content_length: output.content_length(),
+        })
+    }
+
+    async fn get_object(&self, bucket: &str, key: &str) -> Result<Vec<u8>, Error> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+        Ok(output.body.collect().await?.into_bytes().to_vec())
+    }
+
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.client
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+        Ok(())
+    }
+
+    async fn copy_object(
+        &self,
+        source: &ObjectIdentification,
+        bucket: &str,
+        key: &str,
+    ) -> Result<ObjectLocation, Error> {
+        copy_object_version(&self.client, source, bucket, key).await
+    }
+
+    async fn delete_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Result<(), Error> {
+        self.client
+            .delete_object()
+            .bucket(bucket)
+            .key(key)
+            .set_version_id(version_id.map(str::to_string))
+            .send()
+            .await
+            .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+        Ok(())
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        all_versions: bool,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+    ) -> Result<ObjectPage, Error> {
+        if all_versions {
+            let output = self
+                .client
+                .list_object_versions()
+                .bucket(bucket)
+                .prefix(prefix)
+                .max_keys(LIST_PAGE_SIZE)
+                .set_key_marker(key_marker.map(str::to_string))
+                .set_version_id_marker(version_id_marker.map(str::to_string))
+                .send()
+                .await
+                .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+            // Delete markers are listed separately and have nothing to validate
+            let noncurrent = output
+                .versions()
+                .unwrap_or_default()
+                .iter()
+                .filter(|version| !version.is_latest())
+                .filter_map(|version| {
+                    Some((
+                        version.key()?.to_string(),
+                        version.version_id()?.to_string(),
+                    ))
+                })
+                .collect();
+            let objects = output
+                .versions()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|version| {
+                    Some(ObjectIdentification {
+                        bucket: bucket.to_string(),
+                        key: version.key()?.to_string(),
+                        version_id: version.version_id().map(str::to_string),
+                        etag: version.e_tag().map(str::to_string),
+                        size: Some(version.size()),
+                        event_time: upload_time(version.last_modified()),
+                        principal_id: None,
+                    })
+                })
+                .collect();
+            let next = match (output.is_truncated(), output.next_key_marker()) {
+                (true, Some(key)) => Some((
+                    key.to_string(),
+                    output.next_version_id_marker().map(str::to_string),
+                )),
+                _ => None,
+            };
+            Ok(ObjectPage {
+                objects,
+                noncurrent,
+                next,
+            })
+        } else {
+            let output = self
+                .client
+                .list_objects_v2()
+                .bucket(bucket)
+                .prefix(prefix)
+                .max_keys(LIST_PAGE_SIZE)
+                .set_start_after(key_marker.map(str::to_string))
+                .send()
+                .await
+                .map_err(|e| Error::from(e.into_service_error().to_string()))?;
+            let objects: Vec<ObjectIdentification> = output
+                .contents()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|object| {
+                    Some(ObjectIdentification {
+                        bucket: bucket.to_string(),
+                        key: object.key()?.to_string(),
+                        version_id: None,
+                        etag: object.e_tag().map(str::to_string),
+                        size: Some(object.size()),
+                        event_time: upload_time(object.last_modified()),
+                        principal_id: None,
+                    })
+                })
+                .collect();
+            // The next page starts after the last key of this one
+            let next = match (output.is_truncated(), objects.last()) {
+                (true, Some(object)) => Some((object.key.clone(), None)),
+                _ => None,
+            };
+            Ok(ObjectPage {
+                objects,
+                noncurrent: BTreeSet::new(),
+                next,
+            })
+        }
+    }
+}
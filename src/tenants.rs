@@ -0,0 +1,205 @@
+use crate::categories::CategoryRules;
+use crate::config::TagNames;
+use crate::routing::RoutingTable;
+use crate::rules::RuleSet;
+use crate::shadow::ShadowConfig;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// What a tenant changes in the configuration, the settings it doesn't set are the ones of the
+// deployment
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantProfile {
+    pub rules: Option<RuleSet>,
+    // Prepended to every tag name, e.g. "finance:" tags "finance:validated"
+    pub tag_namespace: Option<String>,
+    pub success_queue_url: Option<String>,
+    pub failure_queue_url: Option<String>,
+    pub categories: Option<Vec<String>>,
+    pub category_rules: Option<CategoryRules>,
+    // Replace the routes of the config, an empty list sends every result to the queues
+    pub routes: Option<RoutingTable>,
+    pub shadow: Option<ShadowConfig>,
+}
+
+impl TenantProfile {
+    pub fn apply_tags(&self, tags: &mut TagNames) {
+        if let Some(namespace) = &self.tag_namespace {
+            for name in [
+                &mut tags.validating,
+                &mut tags.validated,
+                &mut tags.valid,
+                &mut tags.quarantine,
+                &mut tags.rejected,
+                &mut tags.warnings,
+            ] {
+                name.insert_str(0, namespace);
+            }
+        }
+    }
+}
+
+// Objects under a prefix of a bucket, or of every bucket when no bucket is set, belong to the
+// tenant of the profile
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TenantMapping {
+    #[serde(default)]
+    pub bucket: Option<String>,
+    #[serde(default)]
+    pub prefix: String,
+    pub profile: String,
+}
+
+impl TenantMapping {
+    fn matches(&self, bucket: &str, key: &str) -> bool {
+        if let Some(expected) = &self.bucket {
+            if expected != bucket {
+                return false;
+            }
+        }
+        key.starts_with(&self.prefix)
+    }
+}
+
+// Resolves the tenant of an object. The mapping with the longest prefix wins, a mapping of
+// the bucket wins over one of every bucket with the same prefix, and objects no mapping
+// matches belong to the default profile.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Tenants {
+    pub default_profile: String,
+    pub profiles: BTreeMap<String, TenantProfile>,
+    #[serde(default)]
+    pub mappings: Vec<TenantMapping>,
+}
+
+impl Tenants {
+    // Name of the profile of the object
+    pub fn resolve(&self, bucket: &str, key: &str) -> &str {
+        self.mappings
+            .iter()
+            .filter(|mapping| mapping.matches(bucket, key))
+            .max_by_key(|mapping| (mapping.prefix.len(), mapping.bucket.is_some()))
+            .map(|mapping| mapping.profile.as_str())
+            .unwrap_or(&self.default_profile)
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&TenantProfile> {
+        self.profiles.get(name)
+    }
+
+    pub fn check(&self) -> Vec<String> {
+        let mut error_messages = Vec::new();
+        if !self.profiles.contains_key(&self.default_profile) {
+            error_messages.push(format!(
+                "Unknown default tenant profile {}",
+                self.default_profile
+            ));
+        }
+        for (index, mapping) in self.mappings.iter().enumerate() {
+            if !self.profiles.contains_key(&mapping.profile) {
+                error_messages.push(format!(
+                    "Tenant mapping {} uses unknown profile {}",
+                    index, mapping.profile
+                ));
+            }
+            if mapping.bucket.as_deref() == Some("") {
+                error_messages.push(format!(
+                    "Tenant mapping {}: bucket must not be empty",
+                    index
+                ));
+            }
+            // Two mappings of the same objects make the tenant depend on their order
+            if self.mappings[..index]
+                .iter()
+                .any(|other| other.bucket == mapping.bucket && other.prefix == mapping.prefix)
+            {
+                error_messages.push(format!(
+                    "Tenant mapping {} maps the same bucket and prefix as an earlier one",
+                    index
+                ));
+            }
+        }
+        error_messages
+    }
+}
+
+#[cfg(test)]
+mod tests_tenants {
+    use super::*;
+
+    fn tenants() -> Tenants {
+        serde_json::from_str(
+            r#"{
+                "default_profile": "platform",
+                "profiles": { "platform": {}, "finance": {}, "payroll": {}, "archive": {} },
+                "mappings": [
+                    { "prefix": "finance/", "profile": "finance" },
+                    { "prefix": "finance/payroll/", "profile": "payroll" },
+                    { "bucket": "archive", "prefix": "", "profile": "archive" },
+                    { "bucket": "archive", "prefix": "finance/", "profile": "archive" }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_most_specific_prefix_wins() {
+        let tenants = tenants();
+        assert!(tenants.check().is_empty());
+        assert_eq!(tenants.resolve("landing", "finance/a.txt"), "finance");
+        assert_eq!(
+            tenants.resolve("landing", "finance/payroll/a.txt"),
+            "payroll"
+        );
+        assert_eq!(tenants.resolve("landing", "hr/a.txt"), "platform");
+        assert_eq!(tenants.resolve("archive", "hr/a.txt"), "archive");
+        // The bucket mapping wins over the one of every bucket with the same prefix
+        assert_eq!(tenants.resolve("archive", "finance/a.txt"), "archive");
+        assert_eq!(
+            tenants.resolve("archive", "finance/payroll/a.txt"),
+            "payroll"
+        );
+    }
+
+    #[test]
+    fn test_tag_namespace() {
+        let profile = TenantProfile {
+            tag_namespace: Some("finance:".to_string()),
+            ..TenantProfile::default()
+        };
+        let mut tags = TagNames::default();
+        profile.apply_tags(&mut tags);
+        assert_eq!(tags.validated, "finance:validated");
+        assert_eq!(tags.quarantine, "finance:quarentine");
+        assert_eq!(tags.warnings, "finance:validation:warnings");
+    }
+
+    #[test]
+    fn test_check_reports_unknown_profiles() {
+        let tenants: Tenants = serde_json::from_str(
+            r#"{
+                "default_profile": "platform",
+                "profiles": { "finance": {} },
+                "mappings": [
+                    { "prefix": "finance/", "profile": "finance" },
+                    { "bucket": "", "prefix": "hr/", "profile": "hr" },
+                    { "prefix": "finance/", "profile": "finance" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            tenants.check(),
+            vec![
+                "Unknown default tenant profile platform",
+                "Tenant mapping 1 uses unknown profile hr",
+                "Tenant mapping 1: bucket must not be empty",
+                "Tenant mapping 2 maps the same bucket and prefix as an earlier one",
+            ]
+        );
+    }
+}
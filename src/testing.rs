@@ -0,0 +1,311 @@
+// Helpers to build S3 events and tag sets and to check the tags of objects in tests.
+// Compiled for the tests of this crate and for other crates with the testing feature.
+use crate::generate_tags::TagSet;
+use crate::memory::MemoryObjectStore;
+use aws_lambda_events::event::s3::{
+    S3Entity, S3Event, S3EventRecord, S3RequestParameters, S3UserIdentity,
+};
+use aws_sdk_s3::model::{Tag, Tagging};
+use aws_sdk_s3::output::GetObjectTaggingOutput;
+use chrono::{DateTime, Utc};
+use lambda_runtime::{Context, LambdaEvent};
+use std::collections::HashMap;
+
+// Builds a Tagging from key value pairs, in order: tagging!["validated" => "true"]
+#[macro_export]
+macro_rules! tagging {
+    () => {
+        $crate::testing::tagging(&[])
+    };
+    ($($key:expr => $value:expr),+ $(,)?) => {
+        $crate::testing::tagging(&[$(($key, $value)),+])
+    };
+}
+
+// Builds the key value pairs of a tag set, as returned by MemoryObjectStore::tags
+#[macro_export]
+macro_rules! tags {
+    () => {
+        Vec::<(String, String)>::new()
+    };
+    ($($key:expr => $value:expr),+ $(,)?) => {
+        vec![$(($key.to_string(), $value.to_string())),+]
+    };
+}
+
+pub fn tagging(tags: &[(&str, &str)]) -> Tagging {
+    if tags.is_empty() {
+        // An empty tag set is built without one, like GenerateTags::remove_tag does
+        return Tagging::builder().build();
+    }
+    Tagging::builder()
+        .set_tag_set(Some(
+            tags.iter()
+                .map(|(key, value)| Tag::builder().key(*key).value(*value).build())
+                .collect(),
+        ))
+        .build()
+}
+
+// What GetObjectTagging returns for these tags
+pub fn tagging_output(tags: &[(&str, &str)]) -> GetObjectTaggingOutput {
+    GetObjectTaggingOutput::builder()
+        .set_tag_set(tagging(tags).tag_set().map(|tags| tags.to_vec()))
+        .build()
+}
+
+// The key value pairs of a tag set, in order
+pub fn tag_pairs<T: TagSet>(tags: &T) -> Vec<(String, String)> {
+    tags.tag_set()
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| {
+            (
+                tag.key().unwrap_or_default().to_string(),
+                tag.value().unwrap_or_default().to_string(),
+            )
+        })
+        .collect()
+}
+
+pub fn tag_value<'a, T: TagSet>(tags: &'a T, key: &str) -> Option<&'a str> {
+    tags.tag_set()
+        .unwrap_or_default()
+        .iter()
+        .find(|tag| tag.key() == Some(key))
+        .and_then(|tag| tag.value())
+}
+
+#[track_caller]
+pub fn assert_tag<T: TagSet>(tags: &T, key: &str, value: &str) {
+    assert_eq!(
+        tag_value(tags, key),
+        Some(value),
+        "tag {} in {:?}",
+        key,
+        tag_pairs(tags)
+    );
+}
+
+#[track_caller]
+pub fn assert_no_tag<T: TagSet>(tags: &T, key: &str) {
+    assert!(
+        tag_value(tags, key).is_none(),
+        "unexpected tag {} in {:?}",
+        key,
+        tag_pairs(tags)
+    );
+}
+
+// The tag set holds exactly these tags, in any order
+#[track_caller]
+pub fn assert_tags<T: TagSet>(tags: &T, expected: &[(&str, &str)]) {
+    assert_same_tags(tag_pairs(tags), expected);
+}
+
+// The latest version of the object exists and holds exactly these tags, in any order
+#[track_caller]
+pub fn assert_object_tags(
+    store: &MemoryObjectStore,
+    bucket: &str,
+    key: &str,
+    expected: &[(&str, &str)],
+) {
+    match store.tags(bucket, key, None) {
+        Some(tags) => assert_same_tags(tags, expected),
+        None => panic!("no object {} in bucket {}", key, bucket),
+    }
+}
+
+#[track_caller]
+fn assert_same_tags(mut actual: Vec<(String, String)>, expected: &[(&str, &str)]) {
+    let mut expected: Vec<(String, String)> = expected
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    actual.sort();
+    expected.sort();
+    assert_eq!(actual, expected);
+}
+
+// A record of an S3 notification, ObjectCreated:Put by default
+#[derive(Debug, Clone)]
+pub struct S3RecordBuilder {
+    bucket: String,
+    key: String,
+    size: Option<i64>,
+    version_id: Option<String>,
+    etag: Option<String>,
+    event_name: String,
+    event_time: DateTime<Utc>,
+    principal_id: Option<String>,
+}
+
+impl S3RecordBuilder {
+    pub fn new(bucket: &str, key: &str) -> S3RecordBuilder {
+        S3RecordBuilder {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            size: Some(0),
+            version_id: None,
+            etag: None,
+            event_name: "ObjectCreated:Put".to_string(),
+            event_time: Utc::now(),
+            principal_id: None,
+        }
+    }
+
+    pub fn size(mut self, size: i64) -> S3RecordBuilder {
+        self.size = Some(size);
+        self
+    }
+
+    // Deletions and some older events carry no size
+    pub fn without_size(mut self) -> S3RecordBuilder {
+        self.size = None;
+        self
+    }
+
+    pub fn version_id(mut self, version_id: &str) -> S3RecordBuilder {
+        self.version_id = Some(version_id.to_string());
+        self
+    }
+
+    pub fn etag(mut self, etag: &str) -> S3RecordBuilder {
+        self.etag = Some(etag.to_string());
+        self
+    }
+
+    pub fn event_name(mut self, event_name: &str) -> S3RecordBuilder {
+        self.event_name = event_name.to_string();
+        self
+    }
+
+    pub fn event_time(mut self, event_time: DateTime<Utc>) -> S3RecordBuilder {
+        self.event_time = event_time;
+        self
+    }
+
+    pub fn principal_id(mut self, principal_id: &str) -> S3RecordBuilder {
+        self.principal_id = Some(principal_id.to_string());
+        self
+    }
+
+    // The s3 part of the record, what the rules are run against
+    pub fn entity(&self) -> S3Entity {
+        let mut s3 = S3Entity::default();
+        s3.bucket.name = Some(self.bucket.clone());
+        s3.bucket.arn = Some(format!("arn:aws:s3:::{}", self.bucket));
+        // S3 URL encodes the key in the event
+        s3.object.key = Some(crate::encode_key(&self.key));
+        s3.object.size = self.size;
+        s3.object.version_id = self.version_id.clone();
+        s3.object.e_tag = self.etag.clone();
+        s3
+    }
+
+    pub fn build(&self) -> S3EventRecord {
+        S3EventRecord {
+            event_version: Some("2.1".to_string()),
+            event_source: Some("aws:s3".to_string()),
+            aws_region: Some("eu-west-1".to_string()),
+            event_time: self.event_time,
+            event_name: Some(self.event_name.clone()),
+            principal_id: S3UserIdentity {
+                principal_id: self.principal_id.clone(),
+            },
+            request_parameters: S3RequestParameters::default(),
+            response_elements: HashMap::new(),
+            s3: self.entity(),
+        }
+    }
+}
+
+// An S3 notification with one or more records
+#[derive(Debug, Clone, Default)]
+pub struct S3EventBuilder {
+    records: Vec<S3EventRecord>,
+}
+
+impl S3EventBuilder {
+    pub fn new() -> S3EventBuilder {
+        S3EventBuilder::default()
+    }
+
+    pub fn record(mut self, record: S3RecordBuilder) -> S3EventBuilder {
+        self.records.push(record.build());
+        self
+    }
+
+    // An ObjectCreated:Put record for the key
+    pub fn object(self, bucket: &str, key: &str, size: i64) -> S3EventBuilder {
+        self.record(S3RecordBuilder::new(bucket, key).size(size))
+    }
+
+    pub fn build(self) -> S3Event {
+        S3Event {
+            records: self.records,
+        }
+    }
+
+    // The event as the runtime hands it to the handler
+    pub fn lambda_event(self, request_id: &str) -> LambdaEvent<S3Event> {
+        let mut context = Context::default();
+        context.request_id = request_id.to_string();
+        LambdaEvent::new(self.build(), context)
+    }
+}
+
+#[cfg(test)]
+mod tests_testing {
+    use super::*;
+    use crate::message::ObjectIdentification;
+    use crate::{decoded_key, is_valid_file};
+
+    #[test]
+    fn test_event_builder() {
+        let event = S3EventBuilder::new()
+            .object("landing", "1234-5678-9012-3456.txt", 42)
+            .record(
+                S3RecordBuilder::new("landing", "finance/report 1.csv")
+                    .version_id("v2")
+                    .event_name("ObjectCreated:Copy")
+                    .without_size(),
+            )
+            .build();
+        assert_eq!(event.records.len(), 2);
+        assert!(is_valid_file(&event.records[0].s3).0);
+
+        let object = ObjectIdentification::from_record(&event.records[1]).unwrap();
+        assert_eq!(object.key, "finance/report 1.csv");
+        assert_eq!(object.version_id.as_deref(), Some("v2"));
+        assert_eq!(object.size, None);
+        assert_eq!(
+            decoded_key(&event.records[1].s3).unwrap(),
+            "finance/report 1.csv"
+        );
+        assert_eq!(
+            event.records[1].event_name.as_deref(),
+            Some("ObjectCreated:Copy")
+        );
+    }
+
+    #[test]
+    fn test_tag_macros_and_assertions() {
+        let tags = crate::tagging!["validated" => "true", "valid" => "true"];
+        assert_eq!(
+            tag_pairs(&tags),
+            crate::tags!["validated" => "true", "valid" => "true"]
+        );
+        assert_tag(&tags, "valid", "true");
+        assert_no_tag(&tags, "quarentine");
+        assert_tags(&tags, &[("valid", "true"), ("validated", "true")]);
+        assert_eq!(crate::tagging![], Tagging::builder().build());
+    }
+
+    #[test]
+    #[should_panic(expected = "tag valid in")]
+    fn test_assert_tag_fails_on_other_value() {
+        assert_tag(&crate::tagging!["valid" => "false"], "valid", "true");
+    }
+}
@@ -0,0 +1,259 @@
+use crate::sink::{Delivery, NotificationSink};
+use crate::sqs::OutboundMessage;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+
+// Headers sent with every webhook call. The signature is the hex encoded
+// HMAC-SHA256 of "{timestamp}.{body}" with the secret of the endpoint.
+pub const SIGNATURE_HEADER: &str = "X-Validation-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Validation-Timestamp";
+// Stable across retries so receivers can drop duplicates
+pub const DELIVERY_ID_HEADER: &str = "X-Validation-Delivery-Id";
+
+pub const DEFAULT_TIMEOUT_MS: u64 = 3000;
+
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// For receivers: check the signature and reject timestamps older than max_age_seconds
+// to prevent replays.
+pub fn verify_signature(
+    secret: &str,
+    timestamp: i64,
+    body: &str,
+    signature: &str,
+    max_age_seconds: i64,
+) -> bool {
+    let signature = match signature
+        .strip_prefix("sha256=")
+        .and_then(|hex_digest| hex::decode(hex_digest).ok())
+    {
+        Some(signature) => signature,
+        None => return false,
+    };
+    if (Utc::now().timestamp() - timestamp).abs() > max_age_seconds {
+        return false;
+    }
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    // verify_slice compares in constant time
+    mac.verify_slice(&signature).is_ok()
+}
+
+// POSTs each message of a batch to an HTTP endpoint, the calls of a batch run concurrently
+pub struct WebhookSink {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub secret: String,
+    pub timeout: Duration,
+}
+
+impl WebhookSink {
+    async fn post(&self, message: &OutboundMessage) -> Delivery {
+        let timestamp = Utc::now().timestamp();
+        let response = self
+            .client
+            .post(&self.url)
+            .timeout(self.timeout)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                sign(&self.secret, timestamp, &message.body),
+            )
+            .header(DELIVERY_ID_HEADER, &message.deduplication_id)
+            .body(message.body.clone())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => {
+                Delivery::Sent(message.deduplication_id.clone())
+            }
+            // Client errors other than throttling won't succeed on a retry
+            Ok(response) => Delivery::Failed {
+                error: format!("Webhook {} answered {}", self.url, response.status()),
+                retryable: response.status().is_server_error()
+                    || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS,
+            },
+            Err(e) => Delivery::Failed {
+                error: format!("Original Error: {}; Could not call webhook {}", e, self.url),
+                retryable: true,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationSink for WebhookSink {
+    async fn send_batch(&self, messages: &[&OutboundMessage]) -> Vec<Delivery> {
+        join_all(messages.iter().map(|message| self.post(message))).await
+    }
+}
+
+#[cfg(test)]
+mod tests_webhook {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    struct ReceivedRequest {
+        headers: BTreeMap<String, String>,
+        body: String,
+    }
+
+    // Minimal HTTP server answering the given status codes in order, one per connection
+    fn serve(statuses: Vec<u16>) -> (String, mpsc::Receiver<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = BTreeMap::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => {
+                            headers.insert(name.to_lowercase(), value.to_string());
+                        }
+                        None => break,
+                    }
+                }
+                let length: usize = headers["content-length"].parse().unwrap();
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                sender
+                    .send(ReceivedRequest {
+                        headers,
+                        body: String::from_utf8(body).unwrap(),
+                    })
+                    .unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    fn sink(url: String) -> WebhookSink {
+        WebhookSink {
+            client: reqwest::Client::new(),
+            url,
+            secret: "s3cr3t".to_string(),
+            timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+        }
+    }
+
+    fn message(body: &str) -> OutboundMessage {
+        OutboundMessage {
+            body: body.to_string(),
+            attributes: BTreeMap::new(),
+            trace_header: None,
+            group_id: "group".to_string(),
+            deduplication_id: "dedup-1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_signature_round_trip() {
+        let timestamp = Utc::now().timestamp();
+        let signature = sign("s3cr3t", timestamp, "{}");
+        assert!(signature.starts_with("sha256="));
+        assert!(verify_signature("s3cr3t", timestamp, "{}", &signature, 300));
+        assert!(!verify_signature("other", timestamp, "{}", &signature, 300));
+        assert!(!verify_signature(
+            "s3cr3t", timestamp, "{ }", &signature, 300
+        ));
+        assert!(!verify_signature(
+            "s3cr3t",
+            timestamp - 600,
+            "{}",
+            &sign("s3cr3t", timestamp - 600, "{}"),
+            300
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_post_signed_message() {
+        let (url, received) = serve(vec![200]);
+        let body = r#"{"message":"File is valid"}"#;
+        let deliveries = sink(url).send_batch(&[&message(body)]).await;
+        assert_eq!(deliveries, vec![Delivery::Sent("dedup-1".to_string())]);
+
+        let request = received.recv().unwrap();
+        assert_eq!(request.body, body);
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert_eq!(request.headers["x-validation-delivery-id"], "dedup-1");
+        let timestamp: i64 = request.headers["x-validation-timestamp"].parse().unwrap();
+        assert!(verify_signature(
+            "s3cr3t",
+            timestamp,
+            &request.body,
+            &request.headers["x-validation-signature"],
+            300
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retryable() {
+        let (url, _received) = serve(vec![503, 400]);
+        let sink = sink(url);
+        let first = sink.send_batch(&[&message("{}")]).await;
+        let second = sink.send_batch(&[&message("{}")]).await;
+        assert!(matches!(
+            first[0],
+            Delivery::Failed {
+                retryable: true,
+                ..
+            }
+        ));
+        assert!(matches!(
+            second[0],
+            Delivery::Failed {
+                retryable: false,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_is_retryable() {
+        // Accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let sink = WebhookSink {
+            timeout: Duration::from_millis(50),
+            ..sink(url)
+        };
+        let deliveries = sink.send_batch(&[&message("{}")]).await;
+        assert!(matches!(
+            deliveries[0],
+            Delivery::Failed {
+                retryable: true,
+                ..
+            }
+        ));
+        drop(listener);
+    }
+}
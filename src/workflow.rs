@@ -0,0 +1,671 @@
+use crate::batch::{EntryResult, MessageBuffer};
+use crate::config::Config;
+use crate::copy::copy_version;
+use crate::generate_tags::GenerateTags;
+use crate::generate_tags::TagSet;
+use crate::message::{
+    encode_message, MessageTag, ObjectIdentification, ObjectLocation, ValidationMessageBody,
+    SCHEMA_VERSION,
+};
+use crate::promotion::promote_object;
+use crate::quarantine::quarantine_object;
+use crate::sink::Notifier;
+use crate::sqs::OutboundMessage;
+use crate::store::ObjectStore;
+use crate::{ValidationIssue, ValidationReport};
+use aws_sdk_s3::model::{Tag, Tagging};
+use chrono::Utc;
+use lambda_runtime::Error;
+use serde::Serialize;
+use std::borrow::Cow;
+
+// Actions taken on an object after its validation, shared by the workflow links handler
+// and the operator tools. They work on a bucket, key and version instead of an S3 event.
+
+pub async fn get_tags(
+    store: &dyn ObjectStore,
+    object: &ObjectIdentification,
+) -> Result<Tagging, Error> {
+    store
+        .get_object_tagging(&object.bucket, &object.key, object.version_id.as_deref())
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+                "Original Error: {}; Could not get tags from Object s3://{}/{} versionId: {}",
+                original_error,
+                object.bucket,
+                object.key,
+                object.version_id.as_deref().unwrap_or("null")
+            ))
+        })
+}
+
+// Value of a tag of the tag set
+fn tag_value<'a>(tags: &'a Tagging, name: &str) -> Option<&'a str> {
+    tags.tag_set()
+        .unwrap_or_default()
+        .iter()
+        .find(|tag| tag.key() == Some(name))
+        .and_then(|tag| tag.value())
+}
+
+// The configuration of the tenant of the object. A quarantined copy keeps the tenant of the
+// object it was made from in a tag, as its own location may belong to another tenant.
+pub async fn tenant_config<'a>(
+    store: &dyn ObjectStore,
+    config: &'a Config,
+    object: &ObjectIdentification,
+) -> Result<Cow<'a, Config>, Error> {
+    let is_quarantined_copy = config
+        .quarantine
+        .as_ref()
+        .is_some_and(|quarantine| quarantine.contains(&object.bucket, &object.key));
+    if config.tenants.is_some() && is_quarantined_copy {
+        let tags = get_tags(store, object).await?;
+        if let Some(tenant) = tag_value(&tags, &config.tags.tenant) {
+            return Ok(config.for_tenant(tenant));
+        }
+    }
+    Ok(config.for_object(&object.bucket, &object.key))
+}
+
+pub async fn put_tags(
+    store: &dyn ObjectStore,
+    object: &ObjectIdentification,
+    tagging: Tagging,
+) -> Result<(), Error> {
+    store
+        .put_object_tagging(
+            &object.bucket,
+            &object.key,
+            object.version_id.as_deref(),
+            tagging,
+        )
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+                "Original Error: {}; Could not put tags on Object s3://{}/{} versionId: {}",
+                original_error,
+                object.bucket,
+                object.key,
+                object.version_id.as_deref().unwrap_or("null")
+            ))
+        })
+}
+
+// Identify an object version that is not described by an S3 event
+pub async fn describe_object(
+    store: &dyn ObjectStore,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+) -> Result<ObjectIdentification, Error> {
+    let head = store
+        .head_object(bucket, key, version_id)
+        .await
+        .map_err(|original_error| {
+            Error::from(format!(
+                "Original Error: {}; Could not find Object s3://{}/{} versionId: {}",
+                original_error,
+                bucket,
+                key,
+                version_id.unwrap_or("null")
+            ))
+        })?;
+    Ok(ObjectIdentification {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        version_id: head.version_id.or(version_id.map(str::to_string)),
+        etag: head.e_tag,
+        size: Some(head.content_length),
+        event_time: Utc::now(),
+        principal_id: None,
+    })
+}
+
+// Where a quarantined copy was moved from, None for an object that was not moved
+fn original_location(
+    config: &Config,
+    object: &ObjectIdentification,
+    tags: &Tagging,
+) -> Result<Option<(String, String)>, Error> {
+    let quarantine = match &config.quarantine {
+        Some(quarantine) if quarantine.contains(&object.bucket, &object.key) => quarantine,
+        _ => return Ok(None),
+    };
+    // Copies in the bucket of the object have no origin tag
+    let bucket = match (tag_value(tags, &config.tags.origin), &quarantine.bucket) {
+        (Some(origin), _) => origin.to_string(),
+        (None, None) => object.bucket.clone(),
+        (None, Some(_)) => {
+            return Err(Error::from(format!(
+                "Object s3://{}/{} has no {} tag, the bucket it was moved from is unknown",
+                object.bucket, object.key, config.tags.origin
+            )))
+        }
+    };
+    let key = object.key[quarantine.prefix.len()..].to_string();
+    Ok(Some((bucket, key)))
+}
+
+// Take the object out of quarantine: it is tagged valid and a success message is sent
+// to the destinations of a valid object, as if it had passed the validation. A copy in the
+// quarantine location is copied back to the key it was moved from, tagged valid so the copy
+// is not validated again, and the rest of the release acts on the restored object. The
+// quarantined copy is tagged valid too, its links are refused from then on.
+pub async fn release(
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+    object: &ObjectIdentification,
+    request_id: &str,
+    message: &str,
+) -> Result<Vec<EntryResult>, Error> {
+    let tenant_config = tenant_config(store, config, object).await?;
+    let config: &Config = &tenant_config;
+    let tags = get_tags(store, object).await?;
+    let original = original_location(config, object, &tags)?;
+    let tags = tags
+        .remove_tag(&config.tags.quarantine)
+        .remove_tag(&config.tags.rejected)
+        .add_true_tag(&config.tags.valid);
+    put_tags(store, object, tags.clone()).await?;
+    let (object, tags) = match original {
+        Some((bucket, key)) => {
+            let location = copy_version(store, object, &bucket, &key).await?;
+            let restored = ObjectIdentification {
+                bucket: location.bucket,
+                key: location.key,
+                version_id: location.version_id,
+                ..object.clone()
+            };
+            // The tags that only make sense on the quarantined copy are removed
+            let tags = tags
+                .remove_tag(&config.tags.tenant)
+                .remove_tag(&config.tags.origin);
+            put_tags(store, &restored, tags.clone()).await?;
+            (restored, tags)
+        }
+        None => (object.clone(), tags),
+    };
+    let object = &object;
+    // A released object is promoted like any valid object
+    let promoted_location = match &config.promotion {
+        Some(promotion) => Some(promote_object(store, promotion, object).await?),
+        None => None,
+    };
+
+    let report = ValidationReport::default();
+    let body = ValidationMessageBody {
+        schema_version: SCHEMA_VERSION.to_string(),
+        workflow: config.workflow(&object.key, &report),
+        exc_id: request_id.to_string(),
+        categories: config.categories(&object.key, &report),
+        message: message.to_string(),
+        object: object.clone(),
+        tags: MessageTag::from_tagging(&tags),
+        continue_url: None,
+        abort_url: None,
+        quarantine_location: None,
+        promoted_location,
+        warnings: report.warnings(),
+    };
+    notify(notifier, config, object, &report, body)
+        .await
+        .map_err(|e| {
+            Error::from(format!(
+                "Released s3://{}/{} but {}",
+                object.bucket, object.key, e
+            ))
+        })
+}
+
+// Outcome of quarantining an object, recorded in the failure message
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Quarantined {
+    pub location: Option<ObjectLocation>,
+    pub continue_url: Option<String>,
+    pub abort_url: Option<String>,
+}
+
+// Move an object tagged for quarantine to the quarantine location when configured, and sign
+// the links of the failure message. The links act on the copy when the object was moved.
+pub async fn quarantine_links(
+    store: &dyn ObjectStore,
+    config: &Config,
+    object: &ObjectIdentification,
+) -> Result<Quarantined, Error> {
+    let location = match &config.quarantine {
+        Some(quarantine) => Some(quarantine_object(store, quarantine, object).await?),
+        None => None,
+    };
+    let quarantined_object = match &location {
+        Some(location) => ObjectIdentification {
+            bucket: location.bucket.clone(),
+            key: location.key.clone(),
+            version_id: location.version_id.clone(),
+            ..object.clone()
+        },
+        None => object.clone(),
+    };
+    // The copy remembers the tenant of the object and the bucket it was moved from when it
+    // is in another bucket, see tenant_config and original_location
+    let origin = config
+        .quarantine
+        .as_ref()
+        .filter(|quarantine| quarantine.bucket.is_some())
+        .map(|_| object.bucket.clone());
+    if location.is_some() && (config.tenant.is_some() || origin.is_some()) {
+        let mut tags = get_tags(store, &quarantined_object)
+            .await?
+            .remove_tag(&config.tags.tenant)
+            .remove_tag(&config.tags.origin)
+            .tag_set()
+            .unwrap_or_default()
+            .to_vec();
+        for (name, value) in [
+            (&config.tags.tenant, &config.tenant),
+            (&config.tags.origin, &origin),
+        ] {
+            if let Some(value) = value {
+                tags.push(Tag::builder().key(name).value(value).build());
+            }
+        }
+        put_tags(
+            store,
+            &quarantined_object,
+            Tagging::builder().set_tag_set(Some(tags)).build(),
+        )
+        .await?;
+    }
+    // Signed links to release or reject the object, when the workflow handler is deployed
+    let (continue_url, abort_url) = match &config.links {
+        Some(links) => {
+            let (continue_url, abort_url) = links.urls(&quarantined_object)?;
+            (Some(continue_url), Some(abort_url))
+        }
+        None => (None, None),
+    };
+    Ok(Quarantined {
+        location,
+        continue_url,
+        abort_url,
+    })
+}
+
+// Put an object back in quarantine, e.g. after it was released by mistake, and send a
+// failure message with the reason.
+pub async fn quarantine(
+    store: &dyn ObjectStore,
+    notifier: &Notifier,
+    config: &Config,
+    object: &ObjectIdentification,
+    request_id: &str,
+    reason: &str,
+) -> Result<Vec<EntryResult>, Error> {
+    let tenant_config = tenant_config(store, config, object).await?;
+    let config: &Config = &tenant_config;
+    let tags = get_tags(store, object)
+        .await?
+        .remove_tag(&config.tags.valid)
+        .remove_tag(&config.tags.rejected)
+        .add_true_tag(&config.tags.quarantine);
+    put_tags(store, object, tags.clone()).await?;
+    let quarantined = quarantine_links(store, config, object).await?;
+
+    let report = ValidationReport {
+        issues: vec![ValidationIssue::new("quarantined_by_operator", reason)],
+    };
+    let body = ValidationMessageBody {
+        schema_version: SCHEMA_VERSION.to_string(),
+        workflow: config.workflow(&object.key, &report),
+        exc_id: request_id.to_string(),
+        categories: config.categories(&object.key, &report),
+        message: report.message(),
+        object: object.clone(),
+        tags: MessageTag::from_tagging(&tags),
+        continue_url: quarantined.continue_url,
+        abort_url: quarantined.abort_url,
+        quarantine_location: quarantined.location,
+        promoted_location: None,
+        warnings: report.warnings(),
+    };
+    notify(notifier, config, object, &report, body)
+        .await
+        .map_err(|e| {
+            Error::from(format!(
+                "Quarantined s3://{}/{} but {}",
+                object.bucket, object.key, e
+            ))
+        })
+}
+
+// Send a message to the destinations of the report, failing if any of them was not notified
+async fn notify(
+    notifier: &Notifier,
+    config: &Config,
+    object: &ObjectIdentification,
+    report: &ValidationReport,
+    body: ValidationMessageBody,
+) -> Result<Vec<EntryResult>, Error> {
+    let outbound_message = OutboundMessage::new(
+        config,
+        encode_message(config, report.outcome(), body)?,
+        object,
+        report,
+        None,
+    );
+    let mut buffer = MessageBuffer::new();
+    for destination in config.destinations(&object.key, report) {
+        buffer.push(destination, 0, outbound_message.clone());
+    }
+    let results = buffer.flush(notifier, config).await;
+
+    let error_messages: Vec<String> = results
+        .iter()
+        .filter_map(|entry| match &entry.result {
+            Ok(_) => None,
+            Err(e) => Some(format!("could not notify {}: {}", entry.destination, e)),
+        })
+        .collect();
+    if !error_messages.is_empty() {
+        return Err(Error::from(error_messages.join(", ")));
+    }
+    Ok(results)
+}
+
+// Where an object stands in the workflow, derived from its tags
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectState {
+    Untagged,
+    Validating,
+    Validated,
+    Valid,
+    Quarantined,
+    Rejected,
+}
+
+impl ObjectState {
+    // The most advanced state wins, a rejected object is also quarantined
+    pub fn from_tags<T: TagSet>(config: &Config, tags: &T) -> ObjectState {
+        let has_tag = |name: &str| {
+            tags.tag_set()
+                .unwrap_or_default()
+                .iter()
+                .any(|tag| tag.key() == Some(name) && tag.value() == Some("true"))
+        };
+        if has_tag(&config.tags.rejected) {
+            ObjectState::Rejected
+        } else if has_tag(&config.tags.quarantine) {
+            ObjectState::Quarantined
+        } else if has_tag(&config.tags.valid) {
+            ObjectState::Valid
+        } else if has_tag(&config.tags.validated) {
+            ObjectState::Validated
+        } else if has_tag(&config.tags.validating) {
+            ObjectState::Validating
+        } else {
+            ObjectState::Untagged
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ObjectStatus {
+    pub object: ObjectIdentification,
+    pub state: ObjectState,
+    pub tags: Vec<MessageTag>,
+}
+
+pub async fn status(
+    store: &dyn ObjectStore,
+    config: &Config,
+    object: &ObjectIdentification,
+) -> Result<ObjectStatus, Error> {
+    let tenant_config = tenant_config(store, config, object).await?;
+    let config: &Config = &tenant_config;
+    let tags = get_tags(store, object).await?;
+    Ok(ObjectStatus {
+        object: object.clone(),
+        state: ObjectState::from_tags(config, &tags),
+        tags: MessageTag::from_tagging(&tags),
+    })
+}
+
+// Mark a quarantined object as rejected, it keeps its quarantine tag
+pub async fn reject(
+    store: &dyn ObjectStore,
+    config: &Config,
+    object: &ObjectIdentification,
+) -> Result<Tagging, Error> {
+    let tenant_config = tenant_config(store, config, object).await?;
+    let config: &Config = &tenant_config;
+    let tags = get_tags(store, object)
+        .await?
+        .remove_tag(&config.tags.valid)
+        .add_true_tag(&config.tags.rejected);
+    put_tags(store, object, tags.clone()).await?;
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests_object_state {
+    use super::*;
+    use crate::generate_tags::GenerateTags;
+
+    #[test]
+    fn test_state_from_tags() {
+        let config = Config::default();
+        let validating = Tagging::tag_as_true("validating");
+        assert_eq!(
+            ObjectState::from_tags(&config, &validating),
+            ObjectState::Validating
+        );
+        let valid = Tagging::tag_as_true("validated").add_true_tag("valid");
+        assert_eq!(ObjectState::from_tags(&config, &valid), ObjectState::Valid);
+        let quarantined = Tagging::tag_as_true("validated").add_true_tag("quarentine");
+        assert_eq!(
+            ObjectState::from_tags(&config, &quarantined),
+            ObjectState::Quarantined
+        );
+        let rejected = quarantined.add_true_tag("rejected");
+        assert_eq!(
+            ObjectState::from_tags(&config, &rejected),
+            ObjectState::Rejected
+        );
+        let released = Tagging::tag_as_false("valid");
+        assert_eq!(
+            ObjectState::from_tags(&config, &released),
+            ObjectState::Untagged
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_tenant_release {
+    use super::*;
+    use crate::handler::function_handler;
+    use crate::memory::{MemoryMessageQueue, MemoryObjectStore};
+    use crate::quarantine::QuarantineConfig;
+    use crate::testing::{assert_object_tags, S3EventBuilder, S3RecordBuilder};
+    use std::sync::Arc;
+
+    const FINANCE_SUCCESS_QUEUE: &str =
+        "https://sqs.eu-west-1.amazonaws.com/123456789012/finance-success";
+
+    fn config() -> Config {
+        Config {
+            success_queue_url: "https://sqs.eu-west-1.amazonaws.com/123456789012/success"
+                .to_string(),
+            failure_queue_url: "https://sqs.eu-west-1.amazonaws.com/123456789012/failure"
+                .to_string(),
+            quarantine: Some(serde_json::from_str::<QuarantineConfig>("{}").unwrap()),
+            tenants: Some(
+                serde_json::from_value(serde_json::json!({
+                    "default_profile": "platform",
+                    "profiles": {
+                        "platform": {},
+                        "finance": {
+                            "tag_namespace": "finance:",
+                            "success_queue_url": FINANCE_SUCCESS_QUEUE,
+                            "categories": ["FIN-OPS"]
+                        }
+                    },
+                    "mappings": [{ "prefix": "finance/", "profile": "finance" }]
+                }))
+                .unwrap(),
+            ),
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_release_moved_object_of_a_tenant() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let notifier = Notifier::new(&aws_config::SdkConfig::builder().build())
+            .with_message_queue(queue.clone());
+        let config = config();
+        let version_id = store.insert("landing", "finance/report.csv", b"data");
+        let event = S3EventBuilder::new()
+            .record(
+                S3RecordBuilder::new("landing", "finance/report.csv")
+                    .size(4)
+                    .version_id(&version_id),
+            )
+            .lambda_event("request-1");
+        function_handler(event, &store, &notifier, &config)
+            .await
+            .unwrap();
+        // The copy is under quarantine/, a prefix of the default tenant
+        assert_object_tags(
+            &store,
+            "landing",
+            "quarantine/finance/report.csv",
+            &[
+                ("finance:validated", "true"),
+                ("finance:quarentine", "true"),
+                ("validation:tenant", "finance"),
+            ],
+        );
+
+        let copy = describe_object(&store, "landing", "quarantine/finance/report.csv", None)
+            .await
+            .unwrap();
+        release(
+            &store,
+            &notifier,
+            &config,
+            &copy,
+            "request-2",
+            "File released from quarantine",
+        )
+        .await
+        .unwrap();
+        assert_object_tags(
+            &store,
+            "landing",
+            "quarantine/finance/report.csv",
+            &[
+                ("finance:validated", "true"),
+                ("finance:valid", "true"),
+                ("validation:tenant", "finance"),
+            ],
+        );
+        let messages = queue.messages_to(FINANCE_SUCCESS_QUEUE);
+        assert_eq!(messages.len(), 1);
+        let body: ValidationMessageBody = serde_json::from_str(&messages[0].body).unwrap();
+        assert_eq!(body.categories, vec!["FIN-OPS"]);
+        assert_eq!(
+            status(&store, &config, &copy).await.unwrap().state,
+            ObjectState::Valid
+        );
+
+        // The file is back at its key, tagged valid, and the message is about it
+        assert_eq!(body.object.key, "finance/report.csv");
+        assert_eq!(
+            store
+                .object("landing", "finance/report.csv", None)
+                .unwrap()
+                .body,
+            b"data"
+        );
+        assert_object_tags(
+            &store,
+            "landing",
+            "finance/report.csv",
+            &[("finance:validated", "true"), ("finance:valid", "true")],
+        );
+        // The copy back is not validated and quarantined again
+        let restored = body.object.version_id.unwrap();
+        let event = S3EventBuilder::new()
+            .record(
+                S3RecordBuilder::new("landing", "finance/report.csv")
+                    .size(4)
+                    .version_id(&restored)
+                    .event_name("ObjectCreated:Copy"),
+            )
+            .lambda_event("request-3");
+        let response = function_handler(event, &store, &notifier, &config)
+            .await
+            .unwrap();
+        assert_eq!(response.records[0].outcome, None);
+        assert!(store
+            .object("landing", "finance/report.csv", None)
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_release_from_another_bucket() {
+        let store = MemoryObjectStore::new();
+        let queue = Arc::new(MemoryMessageQueue::new());
+        let notifier = Notifier::new(&aws_config::SdkConfig::builder().build())
+            .with_message_queue(queue.clone());
+        let config = Config {
+            quarantine: Some(
+                serde_json::from_str(
+                    r#"{ "bucket": "quarantine", "prefix": "", "original": "delete" }"#,
+                )
+                .unwrap(),
+            ),
+            tenants: None,
+            ..config()
+        };
+        let version_id = store.insert("landing", "report.csv", b"data");
+        let event = S3EventBuilder::new()
+            .record(
+                S3RecordBuilder::new("landing", "report.csv")
+                    .size(4)
+                    .version_id(&version_id),
+            )
+            .lambda_event("request-1");
+        function_handler(event, &store, &notifier, &config)
+            .await
+            .unwrap();
+        assert!(store.object("landing", "report.csv", None).is_none());
+        assert_object_tags(
+            &store,
+            "quarantine",
+            "report.csv",
+            &[
+                ("validated", "true"),
+                ("quarentine", "true"),
+                ("validation:origin", "landing"),
+            ],
+        );
+
+        let copy = describe_object(&store, "quarantine", "report.csv", None)
+            .await
+            .unwrap();
+        release(&store, &notifier, &config, &copy, "request-2", "Released")
+            .await
+            .unwrap();
+        assert_object_tags(
+            &store,
+            "landing",
+            "report.csv",
+            &[("validated", "true"), ("valid", "true")],
+        );
+    }
+}